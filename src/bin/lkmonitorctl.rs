@@ -1,9 +1,12 @@
 use std::os::unix::net::UnixStream;
 use std::io::{self, Write, BufRead, BufReader};
 use std::process::exit;
+use clap::builder::PossibleValuesParser;
 use clap::{Parser, Subcommand};
 
 use linux_kernel_monitor::cli::SOCKET_PATH;
+use linux_kernel_monitor::interval::parse_interval;
+use linux_kernel_monitor::metric::get_collectors;
 
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
@@ -21,38 +24,48 @@ enum Commands {
         #[clap(long, help = "Show metrics with specified state (state=[running, disabled])", value_parser = validate_state)]
         state: Option<String>,
     },
-    #[clap(about = "Starts a metric thread (cpu, memory, io)")]
+    #[clap(about = "Starts a metric thread")]
     Start {
-        #[clap(long, help = "Type of metric (cpu, memory, io)", value_parser = validate_metric)]
+        #[clap(long, help = "Type of metric", value_parser = metric_parser())]
         metric: Option<String>,
-        #[clap(long, help = "Refresh rate in seconds")]
-        rate: Option<u8>,
+        #[clap(long, help = "Refresh interval, e.g. 250ms, 10s or 5m (a plain number is in seconds)", value_parser = validate_rate)]
+        rate: Option<String>,
     },
-    #[clap(about = "Stops a metric thread (cpu, memory, io)")]
+    #[clap(about = "Stops a metric thread")]
     Stop {
-        #[clap(long, help = "Type of metric to remove (cpu, memory, io)", value_parser = validate_metric)]
+        #[clap(long, help = "Type of metric to remove", value_parser = metric_parser())]
         metric: Option<String>,
     },
-    #[clap(about = "Set config for metric (cpu, memory, io)")]
+    #[clap(about = "Set config for metric")]
     Set {
-        #[clap(long, help = "Type of metric (cpu, memory, io)", value_parser = validate_metric)]
+        #[clap(long, help = "Type of metric", value_parser = metric_parser())]
         metric: String,
-        #[clap(long, help = "Refresh rate in seconds")]
-        rate: Option<u8>,
+        #[clap(long, help = "Refresh interval, e.g. 250ms, 10s or 5m (a plain number is in seconds)", value_parser = validate_rate)]
+        rate: Option<String>,
         #[clap(long, help = "Start metric on service launch")]
         enabled: Option<bool>,
     },
     #[clap(about = "Store current config to file")]
     Store { },
+    #[clap(about = "Collects a metric right away")]
+    Collect {
+        #[clap(long, help = "Type of metric", value_parser = metric_parser())]
+        metric: Option<String>,
+    },
+    #[clap(about = "Resets the counters and rate baselines of a metric")]
+    Reset {
+        #[clap(long, help = "Type of metric", value_parser = metric_parser())]
+        metric: Option<String>,
+    },
 }
 
-fn validate_metric(metric: &str) -> Result<String, String> {
-    let allowed_metrics = vec!["cpu", "memory", "io"];
-    if allowed_metrics.contains(&metric) {
-       Ok(metric.to_string())
-    } else {
-        Err(format!("Invalid metric: '{}'. Allowed values are: {:?}", metric, allowed_metrics))
-    }
+/// Metrics of the collector registry, clap lists them in the help and in errors.
+fn metric_parser() -> PossibleValuesParser {
+    PossibleValuesParser::new(get_collectors().iter().map(|spec| spec.name))
+}
+
+fn validate_rate(rate: &str) -> Result<String, String> {
+    parse_interval(rate).map(|_| rate.to_string())
 }
 
 fn validate_state(state: &str) -> Result<String, String> {
@@ -67,56 +80,54 @@ fn validate_state(state: &str) -> Result<String, String> {
 fn main() -> Result<(), io::Error> {
     let mut stream = UnixStream::connect(SOCKET_PATH)?;
     let cli = Cli::parse();
-    let mut command = String::new();
-
-    match cli.command {
+    let command = match cli.command {
         Commands::List { state } => {
             match state {
                 Some(state) => {
-                    command = format!("list {}\n", state)
+                    format!("list {}\n", state)
                 },
                 None => {
-                    command = String::from("list\n")
+                    String::from("list\n")
                 }
             }
         },
         Commands::Start { metric, rate } => {
             match (metric, rate) {
                 (Some(metric), Some(rate)) => {
-                    command = format!("start {} {}\n", metric, rate)
+                    format!("start {} {}\n", metric, rate)
                 },
                 (Some(metric), None) => {
-                    command = format!("start {}\n", metric)
+                    format!("start {}\n", metric)
                 },
                 (None, Some(_)) => {
                     eprintln!("Error: only rate option provided");
                     exit(0)
                 },
                 (None, None) => {
-                    command = String::from("start\n")
+                    String::from("start\n")
                 }
             }
         },
         Commands::Stop { metric } => {
             match metric {
                 Some(metric) => {
-                    command = format!("stop {}\n", metric)
+                    format!("stop {}\n", metric)
                 },
                 None => {
-                    command = String::from("stop\n")
+                    String::from("stop\n")
                 }
             }
         }
         Commands::Set { metric, rate, enabled } => {
             match (metric, rate, enabled) {
                 (metric, Some(rate), Some(enabled)) => {
-                    command = format!("set {} {} {}\n", metric, rate, enabled)
+                    format!("set {} {} {}\n", metric, rate, enabled)
                 },
                 (metric, Some(rate), None) => {
-                    command = format!("set {} {}\n", metric, rate)
+                    format!("set {} {}\n", metric, rate)
                 },
                 (metric, None, Some(enabled)) => {
-                    command = format!("set {} {}\n", metric, enabled)
+                    format!("set {} {}\n", metric, enabled)
                 }
                 (_, None, None) => {
                     eprintln!("Error: only metric option provided");
@@ -125,11 +136,23 @@ fn main() -> Result<(), io::Error> {
             }
         },
         Commands::Store { } => {
-            command = String::from("store\n");
-        }
-    }
+            String::from("store\n")
+        },
+        Commands::Collect { metric } => {
+            match metric {
+                Some(metric) => format!("collect {}\n", metric),
+                None => String::from("collect\n"),
+            }
+        },
+        Commands::Reset { metric } => {
+            match metric {
+                Some(metric) => format!("reset {}\n", metric),
+                None => String::from("reset\n"),
+            }
+        },
+    };
 
-    if !command.len() > 0 {
+    if !command.is_empty() {
         stream.write_all(command.as_bytes())?;
         stream.flush()?;
 
@@ -4,13 +4,15 @@ use std::sync::{Arc, Mutex};
 use std::os::unix::net::{UnixListener, UnixStream};
 use std::path::Path;
 use std::thread::JoinHandle;
+use std::time::Duration;
 
 use crossbeam_channel::{unbounded, Receiver, Sender, TryRecvError};
 
-use crate::metric::{MetricType, MetricState, str_to_metric, get_metric_types, str_to_state};
+use crate::interval::parse_interval;
+use crate::metric::{CollectorSpec, MetricState, find_collector, get_collectors, str_to_state};
 
 
-pub static SOCKET_PATH: &'static str = "/var/run/lkmonitor.sock";
+pub static SOCKET_PATH: &str = "/var/run/lkmonitor.sock";
 
 #[derive(Clone, Copy, Debug)]
 pub enum Command {
@@ -19,32 +21,34 @@ pub enum Command {
     List,
     Set,
     Store,
+    Collect,
+    Reset,
     None,
 }
 
 #[derive(Debug)]
 pub struct CliCommand {
-    metric_type: Option<MetricType>,
+    metric: Option<&'static CollectorSpec>,
     cmd: Command,
-    refresh_rate: Option<u8>,
+    refresh_rate: Option<Duration>,
     enabled: Option<bool>,
     state: Option<MetricState>,
 }
 
 impl CliCommand {
-    pub fn new(cmd: Command, metric_type: Option<MetricType>, refresh_rate: Option<u8>, enabled: Option<bool>, state: Option<MetricState>) -> CliCommand {
-        CliCommand { cmd, metric_type, refresh_rate, enabled, state }
+    pub fn new(cmd: Command, metric: Option<&'static CollectorSpec>, refresh_rate: Option<Duration>, enabled: Option<bool>, state: Option<MetricState>) -> CliCommand {
+        CliCommand { cmd, metric, refresh_rate, enabled, state }
     }
 
     pub fn get_command(&self) -> Command {
         self.cmd
     }
 
-    pub fn get_metric_type(&self) -> Option<MetricType> {
-        self.metric_type
+    pub fn get_metric(&self) -> Option<&'static CollectorSpec> {
+        self.metric
     }
 
-    pub fn get_refresh_rate(&self) -> Option<u8> {
+    pub fn get_refresh_rate(&self) -> Option<Duration> {
         self.refresh_rate
     }
 
@@ -64,11 +68,17 @@ pub struct Cli {
     lkm_sender: Arc<Sender<Vec<String>>>,
 }
 
+impl Default for Cli {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Cli {
     pub fn new() -> Self {
         let socket = Path::new(SOCKET_PATH);
         if socket.exists() {
-            fs::remove_file(socket);
+            let _ = fs::remove_file(socket);
         }
         let listener = UnixListener::bind(socket).unwrap();
 
@@ -108,7 +118,7 @@ impl Cli {
     }
 
     pub fn send_service_response(&self, result: Vec<String>) {
-        self.lkm_sender.send(result);
+        let _ = self.lkm_sender.send(result);
     }
 }
 
@@ -127,7 +137,9 @@ impl CliServer {
             while let Some(stream) = self.listener.incoming().next() {
                 match stream {
                     Ok(mut stream) => {
-                        self.handle_cli_client(&mut stream);
+                        if let Err(e) = self.handle_cli_client(&mut stream) {
+                            eprintln!("Cli client failed: {}", e);
+                        }
                     }
                     Err(e) => {
                         eprintln!("Cli connection failed: {}", e);
@@ -138,11 +150,15 @@ impl CliServer {
     }
 
     fn handle_cli_client(&mut self, stream: &mut UnixStream) -> Result<(), std::io::Error> {
-        let command_str = self.read_cli_command(&stream)?;
-        let cli_commands = parse_args(command_str);
-        self.send_cli_command(cli_commands);
-
-        let response = self.receive_service_response();
+        let command_str = self.read_cli_command(stream)?;
+        let response = match parse_args(command_str) {
+            Ok(cli_commands) => {
+                self.send_cli_command(cli_commands);
+                self.receive_service_response()
+            },
+            // rejected before reaching the monitor
+            Err(message) => vec![message],
+        };
         self.send_service_response(stream, response)?;
         Ok(())
     }
@@ -175,7 +191,7 @@ impl CliServer {
     }
 
     fn send_cli_command(&self, cli_commands: Vec<CliCommand>) {
-        self.cli_sender.send(cli_commands);
+        let _ = self.cli_sender.send(cli_commands);
     }
 
     fn read_cli_command(&mut self, stream: &UnixStream) -> Result<String, std::io::Error> {
@@ -191,7 +207,7 @@ impl CliServer {
     }
 }
 
-fn parse_args(line: String) -> Vec<CliCommand> {
+fn parse_args(line: String) -> Result<Vec<CliCommand>, String> {
     let mut commands: Vec<CliCommand> = Vec::new();
 
     let mut args = line.split_whitespace().map(|s| s.to_string()).collect::<Vec<String>>();
@@ -199,10 +215,10 @@ fn parse_args(line: String) -> Vec<CliCommand> {
 
     match command {
         Command::Start => {
-            commands = parse_start_command(&mut args);
+            commands = parse_start_command(&mut args)?;
         },
-        Command::Stop => {
-            commands = parse_stop_command(&mut args);
+        Command::Stop | Command::Collect | Command::Reset => {
+            commands = parse_metric_command(command, &mut args);
         },
         Command::List => {
             commands = parse_list_command(&mut args);
@@ -219,54 +235,70 @@ fn parse_args(line: String) -> Vec<CliCommand> {
         },
     }
 
-    commands
+    Ok(commands)
 }
 
-fn parse_start_command(args: &mut Vec<String>) -> Vec<CliCommand> {
+/// `start [metric [rate [enabled]]]`, an invalid rate or enabled flag rejects the command.
+fn parse_start_command(args: &mut Vec<String>) -> Result<Vec<CliCommand>, String> {
     let mut commands: Vec<CliCommand> = Vec::new();
 
-    let mut metric_type: Option<MetricType> = None;
-    let mut refresh_rate: Option<u8> = None;
+    let mut metric: Option<&'static CollectorSpec> = None;
+    let mut refresh_rate: Option<Duration> = None;
     let mut enabled: Option<bool> = None;
 
     if !args.is_empty() {
-        metric_type = Some(str_to_metric(args.remove(0).as_str()));
+        metric = find_collector(args.remove(0).as_str());
+        if metric.is_none() {
+            return Ok(commands);
+        }
     }
+    let name = metric.map(|spec| spec.name).unwrap_or_default();
     if !args.is_empty() {
-        refresh_rate = Some(args.remove(0).parse::<u8>().unwrap());
+        refresh_rate = match parse_interval(&args.remove(0)) {
+            Ok(refresh_rate) => Some(refresh_rate),
+            Err(e) => return Err(format!("Metric '{}' not started: {};", name, e)),
+        };
     }
     if !args.is_empty() {
-        enabled = Some(args.remove(0).parse::<bool>().unwrap());
+        let arg = args.remove(0);
+        enabled = match arg.parse::<bool>() {
+            Ok(enabled) => Some(enabled),
+            Err(_) => return Err(format!("Metric '{}' not started: invalid enabled flag '{}', expected true or false;", name, arg)),
+        };
     }
 
-    if metric_type.is_some() {
-        let cli_command = CliCommand::new(Command::Start, metric_type, refresh_rate, enabled, None);
+    if metric.is_some() {
+        let cli_command = CliCommand::new(Command::Start, metric, refresh_rate, enabled, None);
         commands.push(cli_command);
     } else {
-        for metric_type in get_metric_types() {
-            let cli_command = CliCommand::new(Command::Start, Some(metric_type), refresh_rate, enabled, None);
+        for spec in get_collectors() {
+            let cli_command = CliCommand::new(Command::Start, Some(spec), refresh_rate, enabled, None);
             commands.push(cli_command);
         }
     }
 
-    commands
+    Ok(commands)
 }
 
-fn parse_stop_command(args: &mut Vec<String>) -> Vec<CliCommand> {
+/// Commands taking an optional metric, without one they apply to every metric.
+fn parse_metric_command(command: Command, args: &mut Vec<String>) -> Vec<CliCommand> {
     let mut commands: Vec<CliCommand> = Vec::new();
 
-    let mut metric_type: Option<MetricType> = None;
+    let mut metric: Option<&'static CollectorSpec> = None;
 
     if !args.is_empty() {
-        metric_type = Some(str_to_metric(args.remove(0).as_str()));
+        metric = find_collector(args.remove(0).as_str());
+        if metric.is_none() {
+            return commands;
+        }
     }
 
-    if metric_type.is_some() {
-        let cli_command = CliCommand::new(Command::Stop, metric_type, None, None, None);
+    if metric.is_some() {
+        let cli_command = CliCommand::new(command, metric, None, None, None);
         commands.push(cli_command);
     } else {
-        for metric_type in get_metric_types() {
-            let cli_command = CliCommand::new(Command::Stop, Some(metric_type), None, None, None);
+        for spec in get_collectors() {
+            let cli_command = CliCommand::new(command, Some(spec), None, None, None);
             commands.push(cli_command);
         }
     }
@@ -287,8 +319,8 @@ fn parse_list_command(args: &mut Vec<String>) -> Vec<CliCommand> {
         let cli_command = CliCommand::new(Command::List, None, None, None, state);
         commands.push(cli_command);
     } else {
-        for metric_type in get_metric_types() {
-            let cli_command = CliCommand::new(Command::List, Some(metric_type), None, None, None);
+        for spec in get_collectors() {
+            let cli_command = CliCommand::new(Command::List, Some(spec), None, None, None);
             commands.push(cli_command);
         }
     }
@@ -299,44 +331,39 @@ fn parse_list_command(args: &mut Vec<String>) -> Vec<CliCommand> {
 fn parse_set_command(args: &mut Vec<String>) -> Vec<CliCommand> {
     let mut commands: Vec<CliCommand> = Vec::new();
 
-    let mut metric_type: Option<MetricType> = None;
-    let mut refresh_rate: Option<u8> = None;
+    let mut metric: Option<&'static CollectorSpec> = None;
+    let mut refresh_rate: Option<Duration> = None;
     let mut enabled: Option<bool> = None;
 
     if !args.is_empty() {
-        metric_type = Some(str_to_metric(args.remove(0).as_str()));
+        metric = find_collector(args.remove(0).as_str());
+        if metric.is_none() {
+            return commands;
+        }
     }
     if !args.is_empty() {
         let arg = args.remove(0);
-        let mut arg_u8_test = arg.parse::<u8>();
-        if arg_u8_test.is_ok() {
-            refresh_rate = Some(arg_u8_test.unwrap());
-        } else {
-             let arg_bool_test = arg.parse::<bool>();
-            if arg_bool_test.is_ok() {
-                enabled = Some(arg_bool_test.unwrap());
-            }
+        if let Ok(interval) = parse_interval(&arg) {
+            refresh_rate = Some(interval);
+        } else if let Ok(arg_bool) = arg.parse::<bool>() {
+            enabled = Some(arg_bool);
         }
     }
     if !args.is_empty() {
         let arg = args.remove(0);
-        let mut arg_u8_test = arg.parse::<u8>();
-        if arg_u8_test.is_ok() {
-            refresh_rate = Some(arg_u8_test.unwrap());
-        } else {
-            let arg_bool_test = arg.parse::<bool>();
-            if arg_bool_test.is_ok() {
-                enabled = Some(arg_bool_test.unwrap());
-            }
+        if let Ok(interval) = parse_interval(&arg) {
+            refresh_rate = Some(interval);
+        } else if let Ok(arg_bool) = arg.parse::<bool>() {
+            enabled = Some(arg_bool);
         }
     }
 
-    if metric_type.is_some() {
-        let cli_command = CliCommand::new(Command::Set, metric_type, refresh_rate, enabled, None);
+    if metric.is_some() {
+        let cli_command = CliCommand::new(Command::Set, metric, refresh_rate, enabled, None);
         commands.push(cli_command);
     } else {
-        for metric_type in get_metric_types() {
-            let cli_command = CliCommand::new(Command::Set, Some(metric_type), refresh_rate, enabled, None);
+        for spec in get_collectors() {
+            let cli_command = CliCommand::new(Command::Set, Some(spec), refresh_rate, enabled, None);
             commands.push(cli_command);
         }
     }
@@ -351,7 +378,29 @@ pub fn get_command(cmd_str: &str) -> Command {
         "list" => Command::List,
         "set" => Command::Set,
         "store" => Command::Store,
+        "collect" => Command::Collect,
+        "reset" => Command::Reset,
         _ => Command::None,
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_start_command_args() {
+        let commands = parse_args(String::from("start cpu 5s false")).unwrap();
+        assert_eq!(commands.len(), 1);
+        assert_eq!(commands[0].get_metric().map(|spec| spec.name), Some("cpu"));
+        assert_eq!(commands[0].get_refresh_rate(), Some(Duration::from_secs(5)));
+        assert_eq!(commands[0].get_enabled(), Some(false));
+
+        assert_eq!(parse_args(String::from("start cpu 5s maybe")).unwrap_err(),
+                   "Metric 'cpu' not started: invalid enabled flag 'maybe', expected true or false;");
+        assert_eq!(parse_args(String::from("start cpu 5x")).unwrap_err(),
+                   "Metric 'cpu' not started: invalid interval unit 'x' in '5x', expected ms, s, m or h;");
+        assert!(parse_args(String::from("start nosuchmetric")).unwrap().is_empty());
+    }
+}
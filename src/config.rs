@@ -1,80 +1,131 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
 use std::path::Path;
+use std::time::Duration;
 
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
-use crate::metric::{get_metric_types, MetricState, MetricType};
+use crate::interval::{format_interval, parse_interval};
+use crate::metric::{get_collectors, CollectorSpec, MetricState};
+use crate::metric::host::HostPaths;
 
 
-pub static CONFIG_PATH: &'static str = "/etc/lkmconfig.toml";
+pub static CONFIG_PATH: &str = "/etc/lkmconfig.toml";
 
-#[derive(Deserialize, Serialize, Copy, Clone, Debug)]
+/// Collector options as stored in the config file, e.g. the `[io_config]` section.
+pub type Options = toml::Table;
+
+/// Converts the typed options of a collector into a config section.
+pub fn to_options<T: Serialize>(options: &T) -> Options {
+    Options::try_from(options).unwrap_or_default()
+}
+
+/// Reads the typed options of a collector from its config section, keys the
+/// options don't know (`enabled`, `refresh_rate`) are ignored.
+pub fn from_options<T: DeserializeOwned>(options: &Options) -> Result<T, String> {
+    options.clone().try_into::<T>().map_err(|e| e.to_string())
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(default)]
 pub struct MetricsConfig {
-    cpu_config: CpuConfig,
-    memory_config: MemoryConfig,
-    io_config: IOConfig,
+    proc_root: String,
+    sys_root: String,
+    /// Collector sections by name, e.g. "cpu_config".
+    #[serde(flatten)]
+    sections: BTreeMap<String, Options>,
 }
 
 impl Default for MetricsConfig {
     fn default() -> Self {
-        Self {
-            cpu_config: CpuConfig::default(),
-            memory_config: MemoryConfig::default(),
-            io_config: IOConfig::default(),
-        }
+        let mut config = Self {
+            proc_root: String::from("/proc"),
+            sys_root: String::from("/sys"),
+            sections: BTreeMap::new(),
+        };
+        config.add_defaults();
+        config
     }
 }
 
 impl MetricsConfig {
-    pub fn get_refresh_rate(&self, metric_type: MetricType) -> u8 {
-        match metric_type {
-            MetricType::CPU => self.cpu_config.get_refresh_rate(),
-            MetricType::Memory => self.memory_config.get_refresh_rate(),
-            MetricType::IO => self.io_config.get_refresh_rate(),
-            MetricType::None => 0,
+    /// Roots collectors read /proc and /sys from, e.g. /host/proc when running in a container.
+    pub fn get_host_paths(&self) -> HostPaths {
+        HostPaths::new(&self.proc_root, &self.sys_root)
+    }
+
+    /// Adds the default values of every registered collector that are missing in
+    /// the config, so a file only needs to hold what differs from the defaults.
+    fn add_defaults(&mut self) {
+        for spec in get_collectors() {
+            let section = self.sections.entry(spec.section.to_string()).or_default();
+            section.entry("enabled").or_insert(toml::Value::Boolean(true));
+            section.entry("refresh_rate").or_insert(toml::Value::String(format_interval(spec.refresh_rate)));
+            for (key, value) in (spec.default_options)() {
+                section.entry(key).or_insert(value);
+            }
         }
     }
 
-    pub fn set_refresh_rate(&mut self, metric_type: MetricType, refresh_rate: u8) {
-        match metric_type {
-            MetricType::CPU => self.cpu_config.set_refresh_rate(refresh_rate),
-            MetricType::Memory => self.memory_config.set_refresh_rate(refresh_rate),
-            MetricType::IO => self.io_config.set_refresh_rate(refresh_rate),
-            MetricType::None => (),
-        }
+    fn get_section(&self, spec: &CollectorSpec) -> Option<&Options> {
+        self.sections.get(spec.section)
     }
 
-    pub fn get_enabled(&self, metric_type: MetricType) -> bool {
-        match metric_type {
-            MetricType::CPU => self.cpu_config.get_enabled(),
-            MetricType::Memory => self.memory_config.get_enabled(),
-            MetricType::IO => self.io_config.get_enabled(),
-            MetricType::None => false,
-        }
+    fn get_section_mut(&mut self, spec: &CollectorSpec) -> &mut Options {
+        self.sections.entry(spec.section.to_string()).or_default()
     }
 
-    pub fn set_enabled(&mut self, metric_type: MetricType, enabled: bool) {
-        match metric_type {
-            MetricType::CPU => self.cpu_config.set_enabled(enabled),
-            MetricType::Memory => self.memory_config.set_enabled(enabled),
-            MetricType::IO => self.io_config.set_enabled(enabled),
-            MetricType::None => (),
-        }
+    /// The config section of the collector, including `enabled` and `refresh_rate`.
+    pub fn get_options(&self, spec: &CollectorSpec) -> Options {
+        self.get_section(spec).cloned().unwrap_or_default()
+    }
+
+    /// The `refresh_rate` of the section, an interval like "250ms" or "5m", or a
+    /// number of seconds as written by older versions.
+    fn read_refresh_rate(&self, spec: &CollectorSpec) -> Option<Result<Duration, String>> {
+        let value = self.get_section(spec)?.get("refresh_rate")?;
+        Some(match value {
+            toml::Value::String(interval) => parse_interval(interval),
+            toml::Value::Integer(secs) => u64::try_from(*secs)
+                .map_err(|e| e.to_string())
+                .and_then(|secs| parse_interval(&secs.to_string())),
+            _ => Err(format!("invalid refresh_rate {}, expected e.g. \"10s\"", value)),
+        })
+    }
+
+    pub fn get_refresh_rate(&self, spec: &CollectorSpec) -> Duration {
+        self.read_refresh_rate(spec).and_then(|refresh_rate| refresh_rate.ok()).unwrap_or(spec.refresh_rate)
+    }
+
+    pub fn set_refresh_rate(&mut self, spec: &CollectorSpec, refresh_rate: Duration) {
+        self.get_section_mut(spec).insert(String::from("refresh_rate"), toml::Value::String(format_interval(refresh_rate)));
+    }
+
+    pub fn get_enabled(&self, spec: &CollectorSpec) -> bool {
+        self.get_section(spec)
+            .and_then(|section| section.get("enabled"))
+            .and_then(|value| value.as_bool())
+            .unwrap_or(true)
+    }
+
+    pub fn set_enabled(&mut self, spec: &CollectorSpec, enabled: bool) {
+        self.get_section_mut(spec).insert(String::from("enabled"), toml::Value::Boolean(enabled));
     }
 }
 
 pub struct MonitorConfig {
     metrics_config: MetricsConfig,
-    states: HashMap<MetricType, MetricState>,
+    states: HashMap<&'static str, MetricState>,
+}
+
+impl Default for MonitorConfig {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl MonitorConfig {
     pub fn new() -> Self {
-        let mut states = HashMap::new();
-        states.insert(MetricType::CPU, MetricState::Initialized);
-        states.insert(MetricType::Memory, MetricState::Initialized);
-        states.insert(MetricType::IO, MetricState::Initialized);
-
         let mut metrics_config = MetricsConfig::default();
 
         let config = Path::new(CONFIG_PATH);
@@ -87,11 +138,12 @@ impl MonitorConfig {
             }
         }
 
-        for metric_type in get_metric_types() {
-            if metrics_config.get_enabled(metric_type) {
-                states.insert(metric_type, MetricState::Initialized);
+        let mut states = HashMap::new();
+        for spec in get_collectors() {
+            if metrics_config.get_enabled(spec) {
+                states.insert(spec.name, MetricState::Initialized);
             } else {
-                states.insert(metric_type, MetricState::Disabled);
+                states.insert(spec.name, MetricState::Disabled);
             }
         }
 
@@ -105,136 +157,95 @@ impl MonitorConfig {
         &self.metrics_config
     }
 
-    pub fn get_state(&self, metric_type: MetricType) -> MetricState {
-        self.states.get(&metric_type).unwrap().clone()
+    pub fn get_state(&self, spec: &CollectorSpec) -> MetricState {
+        self.states.get(spec.name).copied().unwrap_or(MetricState::Disabled)
     }
 
-    pub fn set_state(&mut self, metric_type: MetricType, state: MetricState) {
-        self.states.insert(metric_type, state);
+    pub fn set_state(&mut self, spec: &'static CollectorSpec, state: MetricState) {
+        self.states.insert(spec.name, state);
     }
 
-    pub fn get_refresh_rate(&self, metric_type: MetricType) -> u8 {
-        self.metrics_config.get_refresh_rate(metric_type)
+    pub fn get_refresh_rate(&self, spec: &CollectorSpec) -> Duration {
+        self.metrics_config.get_refresh_rate(spec)
     }
 
-    pub fn set_refresh_rate(&mut self, metric_type: MetricType, refresh_rate: u8) {
-        self.metrics_config.set_refresh_rate(metric_type, refresh_rate);
+    pub fn set_refresh_rate(&mut self, spec: &CollectorSpec, refresh_rate: Duration) {
+        self.metrics_config.set_refresh_rate(spec, refresh_rate);
     }
 
-    pub fn get_enabled(&self, metric_type: MetricType) -> bool {
-        self.metrics_config.get_enabled(metric_type)
+    pub fn get_enabled(&self, spec: &CollectorSpec) -> bool {
+        self.metrics_config.get_enabled(spec)
     }
 
-    pub fn set_enabled(&mut self, metric_type: MetricType, enabled: bool) {
-        self.metrics_config.set_enabled(metric_type, enabled);
+    pub fn set_enabled(&mut self, spec: &CollectorSpec, enabled: bool) {
+        self.metrics_config.set_enabled(spec, enabled);
     }
 }
 
 fn read_config(path: &str) -> Result<MetricsConfig, std::io::Error> {
     let config_str = fs::read_to_string(path)?;
 
-    match toml::from_str(&config_str) {
-        Ok(config) => Ok(config),
-        Err(e) => Err(std::io::Error::new(std::io::ErrorKind::Other, e)),
-    }
-}
-
-#[derive(Deserialize, Serialize, Copy, Clone, Debug)]
-pub struct CpuConfig {
-    enabled: bool,
-    refresh_rate: u8,
-}
-
-impl Default for CpuConfig {
-    fn default() -> Self {
-        CpuConfig {
-            enabled: true,
-            refresh_rate: 1,
+    let mut config: MetricsConfig = toml::from_str(&config_str).map_err(std::io::Error::other)?;
+    for section in config.sections.keys() {
+        if !get_collectors().iter().any(|spec| spec.section == section) {
+            eprintln!("{}: unknown section '{}'", path, section);
         }
     }
-}
-
-impl CpuConfig {
-    pub fn set_enabled(&mut self, enabled: bool) {
-        self.enabled = enabled;
-    }
-
-    pub fn get_enabled(&self) -> bool {
-        self.enabled
-    }
-
-    pub fn get_refresh_rate(&self) -> u8 {
-        self.refresh_rate
-    }
-
-    pub fn set_refresh_rate(&mut self, refresh_rate: u8) {
-        self.refresh_rate = refresh_rate;
-    }
-}
-
-#[derive(Deserialize, Serialize, Copy, Clone, Debug)]
-pub struct MemoryConfig {
-    enabled: bool,
-    refresh_rate: u8,
-}
-
-impl Default for MemoryConfig {
-    fn default() -> Self {
-        MemoryConfig {
-            enabled: true,
-            refresh_rate: 1,
+    for spec in get_collectors() {
+        if let Some(Err(e)) = config.read_refresh_rate(spec) {
+            eprintln!("{}: [{}] {}, using {}", path, spec.section, e, format_interval(spec.refresh_rate));
         }
     }
-}
-
-impl MemoryConfig {
-    pub fn set_enabled(&mut self, enabled: bool) {
-        self.enabled = enabled;
-    }
-
-    pub fn get_enabled(&self) -> bool {
-        self.enabled
-    }
-
-    pub fn get_refresh_rate(&self) -> u8 {
-        self.refresh_rate
-    }
+    config.add_defaults();
 
-    pub fn set_refresh_rate(&mut self, refresh_rate: u8) {
-        self.refresh_rate = refresh_rate;
-    }
+    Ok(config)
 }
 
-#[derive(Deserialize, Serialize, Copy, Clone, Debug)]
-pub struct IOConfig {
-    enabled: bool,
-    refresh_rate: u8,
-}
-
-impl Default for IOConfig {
-    fn default() -> Self {
-        IOConfig {
-            enabled: true,
-            refresh_rate: 1,
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metric::find_collector;
+
+    #[test]
+    fn partial_config_gets_defaults() {
+        let mut config: MetricsConfig = toml::from_str("proc_root = \"/host/proc\"\n\n[io_config]\nrefresh_rate = 5\nexclude_devices = [\"sr\"]\n").unwrap();
+        config.add_defaults();
+
+        let io = find_collector("io").unwrap();
+        assert_eq!(config.get_refresh_rate(io), Duration::from_secs(5));
+        assert!(config.get_enabled(io));
+        let options = config.get_options(io);
+        assert_eq!(options["exclude_devices"].as_array().unwrap().len(), 1);
+        assert_eq!(options["include_partitions"].as_bool(), Some(false));
+
+        let storage = find_collector("storage").unwrap();
+        assert_eq!(config.get_refresh_rate(storage), Duration::from_secs(30));
+        assert_eq!(config.get_host_paths().resolve("/proc/stat"), Path::new("/host/proc/stat"));
+    }
+
+    #[test]
+    fn stored_config_reads_back() {
+        let mut config = MetricsConfig::default();
+        let kmsg = find_collector("kmsg").unwrap();
+        config.set_enabled(kmsg, false);
+        config.set_refresh_rate(kmsg, Duration::from_millis(250));
+
+        let stored = toml::to_string(&config).unwrap();
+        assert!(stored.contains("refresh_rate = \"250ms\""));
+        let config: MetricsConfig = toml::from_str(&stored).unwrap();
+        assert!(!config.get_enabled(kmsg));
+        assert_eq!(config.get_refresh_rate(kmsg), Duration::from_millis(250));
+        assert_eq!(config.get_options(kmsg)["path"].as_str(), Some("/dev/kmsg"));
+    }
+
+    #[test]
+    fn refresh_rate_as_interval() {
+        let config: MetricsConfig = toml::from_str("[cpu_config]\nrefresh_rate = \"5m\"\n\n[memory_config]\nrefresh_rate = \"1ms\"\n").unwrap();
+
+        assert_eq!(config.get_refresh_rate(find_collector("cpu").unwrap()), Duration::from_secs(300));
+        // too short, the default is used
+        let memory = find_collector("memory").unwrap();
+        assert!(config.read_refresh_rate(memory).unwrap().is_err());
+        assert_eq!(config.get_refresh_rate(memory), Duration::from_secs(1));
     }
 }
-
-impl IOConfig {
-    pub fn set_enabled(&mut self, enabled: bool) {
-        self.enabled = enabled;
-    }
-
-    pub fn get_enabled(&self) -> bool {
-        self.enabled
-    }
-
-    pub fn get_refresh_rate(&self) -> u8 {
-        self.refresh_rate
-    }
-
-    pub fn set_refresh_rate(&mut self, refresh_rate: u8) {
-        self.refresh_rate = refresh_rate;
-    }
-
-}
\ No newline at end of file
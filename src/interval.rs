@@ -0,0 +1,93 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+
+/// Shortest collection interval, below it collecting would take most of the time.
+pub const MIN_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Parses an interval like "250ms", "10s", "5m" or "1h", a plain number is in
+/// seconds as refresh rates used to be.
+pub fn parse_interval(interval_str: &str) -> Result<Duration, String> {
+    let interval_str = interval_str.trim();
+    let split = interval_str.find(|c: char| !c.is_ascii_digit()).unwrap_or(interval_str.len());
+    let (value, unit) = interval_str.split_at(split);
+    let value = value.parse::<u64>().map_err(|_| format!("invalid interval '{}', expected e.g. 250ms, 10s or 5m", interval_str))?;
+
+    let interval = match unit {
+        "ms" => Duration::from_millis(value),
+        "" | "s" => Duration::from_secs(value),
+        "m" => Duration::from_secs(value.saturating_mul(60)),
+        "h" => Duration::from_secs(value.saturating_mul(3600)),
+        _ => return Err(format!("invalid interval unit '{}' in '{}', expected ms, s, m or h", unit, interval_str)),
+    };
+    if interval < MIN_INTERVAL {
+        return Err(format!("interval '{}' is shorter than {}", interval_str, format_interval(MIN_INTERVAL)));
+    }
+
+    Ok(interval)
+}
+
+/// Formats an interval in the largest unit that represents it exactly, e.g. "5m" or "1500ms".
+pub fn format_interval(interval: Duration) -> String {
+    let millis = interval.as_millis();
+    if millis == 0 || !millis.is_multiple_of(1000) {
+        return format!("{}ms", millis);
+    }
+    let secs = interval.as_secs();
+    if secs.is_multiple_of(3600) {
+        format!("{}h", secs / 3600)
+    } else if secs.is_multiple_of(60) {
+        format!("{}m", secs / 60)
+    } else {
+        format!("{}s", secs)
+    }
+}
+
+/// Time from `now` to the next multiple of `interval` since the epoch, so
+/// collectors with the same interval collect at the same instants and the
+/// collection time doesn't shift later ticks. Ticks missed by a collection
+/// longer than the interval are skipped.
+pub fn until_next_tick(now: SystemTime, interval: Duration) -> Duration {
+    let interval = interval.max(MIN_INTERVAL).as_nanos();
+    let since_epoch = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos();
+    let remaining = interval - since_epoch % interval;
+    Duration::from_nanos(remaining as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_interval_units() {
+        assert_eq!(parse_interval("250ms"), Ok(Duration::from_millis(250)));
+        assert_eq!(parse_interval("10s"), Ok(Duration::from_secs(10)));
+        assert_eq!(parse_interval("10"), Ok(Duration::from_secs(10)));
+        assert_eq!(parse_interval("5m"), Ok(Duration::from_secs(300)));
+        assert_eq!(parse_interval("2h"), Ok(Duration::from_secs(7200)));
+        assert!(parse_interval("5ms").is_err());
+        assert!(parse_interval("0").is_err());
+        assert!(parse_interval("1.5s").is_err());
+        assert!(parse_interval("5d").is_err());
+        assert!(parse_interval("s").is_err());
+    }
+
+    #[test]
+    fn format_interval_reads_back() {
+        for interval in ["250ms", "1500ms", "1s", "90s", "5m", "1h", "300"] {
+            let parsed = parse_interval(interval).unwrap();
+            assert_eq!(parse_interval(&format_interval(parsed)), Ok(parsed));
+        }
+        assert_eq!(format_interval(Duration::from_secs(300)), "5m");
+        assert_eq!(format_interval(Duration::from_millis(1500)), "1500ms");
+    }
+
+    #[test]
+    fn ticks_are_aligned_to_the_epoch() {
+        let at = |millis: u64| UNIX_EPOCH + Duration::from_millis(millis);
+        let interval = Duration::from_millis(250);
+        assert_eq!(until_next_tick(at(1_000), interval), interval);
+        assert_eq!(until_next_tick(at(1_010), interval), Duration::from_millis(240));
+        assert_eq!(until_next_tick(at(1_249), interval), Duration::from_millis(1));
+        assert_eq!(until_next_tick(at(61_000), Duration::from_secs(60)), Duration::from_secs(59));
+    }
+}
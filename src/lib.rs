@@ -1,6 +1,8 @@
 pub mod metric;
 pub mod config;
+pub mod interval;
 pub mod queue;
+pub mod sample;
 pub mod logger;
 pub mod cli;
 
@@ -8,15 +10,14 @@ use std::sync::Arc;
 use std::thread;
 use std::{fs, fs::File};
 use std::path::Path;
-use clap::builder::TypedValueParser;
 use crossbeam_channel::unbounded;
 
 use queue::Queue;
 use config::{MonitorConfig, CONFIG_PATH};
 use logger::Logger;
 use cli::{Cli, CliCommand, Command};
-use metric::{Metric, MetricCollector, MetricState, MetricType,
-             get_metric_collector, get_metric_types, metric_to_str, state_to_str};
+use interval::format_interval;
+use metric::{ControlCommand, Metric, MetricState, get_collectors, state_to_str};
 
 pub struct LinuxKernelMonitor {
     config: MonitorConfig,
@@ -32,15 +33,21 @@ impl LinuxKernelMonitor {
         let mut config = MonitorConfig::new();
 
         let mut metrics = Vec::new();
-        for metric_type in get_metric_types() {
-            if metric_type == MetricType::None {
-                continue;
-            }
+        let paths = config.get_config().get_host_paths();
+        for spec in get_collectors() {
+            let collector = match (spec.new)(&config.get_config().get_options(spec), &paths) {
+                Ok(collector) => collector,
+                Err(e) => {
+                    eprintln!("{}: invalid config section '{}': {}", spec.name, spec.section, e);
+                    config.set_state(spec, MetricState::Disabled);
+                    continue;
+                },
+            };
 
-            let (tx, rx) = unbounded::<String>();
-            let metric = Metric::new(get_metric_collector(metric_type).unwrap(), metric_type, queue.clone(), Arc::new(tx), Arc::new(rx), None);
-            if config.get_state(metric.get_type()) != MetricState::Disabled {
-                config.set_state(metric_type, MetricState::Initialized);
+            let (tx, rx) = unbounded::<ControlCommand>();
+            let metric = Metric::new(collector, spec, queue.clone(), Arc::new(tx), Arc::new(rx), None);
+            if config.get_state(spec) != MetricState::Disabled {
+                config.set_state(spec, MetricState::Initialized);
             }
             metrics.push(metric);
         }
@@ -67,9 +74,10 @@ impl LinuxKernelMonitor {
 
     fn launch_metrics(&mut self) {
         for metric in self.metrics.iter_mut() {
-            if self.config.get_state(metric.get_type()) != MetricState::Disabled {
-                metric.start(self.config.get_refresh_rate(metric.get_type()));
-                self.config.set_state(metric.get_type(), MetricState::Running)
+            let spec = metric.get_spec();
+            if self.config.get_state(spec) != MetricState::Disabled {
+                metric.start(self.config.get_refresh_rate(spec));
+                self.config.set_state(spec, MetricState::Running)
             }
         }
     }
@@ -77,7 +85,7 @@ impl LinuxKernelMonitor {
     fn launch_logger(&mut self) {
         let logger_ref = self.logger.get_log_collector();
         let handle = thread::spawn(move || {
-            let mut log_guard = logger_ref.lock().unwrap();
+            let log_guard = logger_ref.lock().unwrap();
             log_guard.run();
         });
         self.logger.add_handle(handle);
@@ -105,6 +113,10 @@ impl LinuxKernelMonitor {
         let mut results = Vec::new();
 
         for cli_command in cli_commands {
+            if let Some(result_str) = self.unavailable_metric(&cli_command) {
+                results.push(result_str);
+                continue;
+            }
             match cli_command.get_command() {
                 Command::Start => {
                     let result_str = self.start_metric(cli_command);
@@ -126,6 +138,10 @@ impl LinuxKernelMonitor {
                     let result_str = self.store_config();
                     results.push(result_str);
                 },
+                Command::Collect | Command::Reset => {
+                    let result_str = self.control_metric(cli_command);
+                    results.push(result_str);
+                },
                 Command::None => { },
             }
         }
@@ -133,56 +149,69 @@ impl LinuxKernelMonitor {
         results
     }
 
+    /// Metrics whose config failed validation at startup have no collector,
+    /// so commands acting on them are answered here.
+    fn unavailable_metric(&self, cli_command: &CliCommand) -> Option<String> {
+        match cli_command.get_command() {
+            Command::Start | Command::Stop | Command::Set | Command::Collect | Command::Reset => {
+                let spec = cli_command.get_metric()?;
+                if self.metrics.iter().any(|metric| metric.get_spec().name == spec.name) {
+                    None
+                } else {
+                    Some(format!("Metric '{}' not available: invalid config;", spec.name))
+                }
+            },
+            _ => None,
+        }
+    }
+
     fn start_metric(&mut self, cli_command: CliCommand) -> String {
         let mut result_str = String::new();
         let refresh_rate = cli_command.get_refresh_rate();
-        let metric_type = cli_command.get_metric_type().unwrap();
-        let metric_state = self.config.get_state(metric_type);
-
-            for metric in self.metrics.iter_mut() {
-                if metric.get_type() == metric_type {
-                    if metric_state == MetricState::Running {
-                        if refresh_rate.is_some() {
-                            if self.config.get_refresh_rate(metric_type) != refresh_rate.unwrap() {
-                                metric.set_refresh_rate(refresh_rate.unwrap());
-                                result_str = format!("'{}' metric 'rate' is set to '{}';", metric_to_str(metric.get_type()), refresh_rate.unwrap());
-                            } else {
-                                result_str = format!("'{}' metric already running with rate '{}';", metric_to_str(metric_type), self.config.get_refresh_rate(metric_type));
-                            }
+        let spec = cli_command.get_metric().unwrap();
+        let metric_state = self.config.get_state(spec);
+
+        for metric in self.metrics.iter_mut() {
+            if metric.get_spec().name == spec.name {
+                if metric_state == MetricState::Running {
+                    if let Some(refresh_rate) = refresh_rate {
+                        if self.config.get_refresh_rate(spec) != refresh_rate {
+                            metric.set_refresh_rate(refresh_rate);
+                            result_str = format!("'{}' metric 'rate' is set to '{}';", spec.name, format_interval(refresh_rate));
                         } else {
-                            result_str = format!("Metric '{}' already running;", metric_to_str(metric_type));
+                            result_str = format!("'{}' metric already running with rate '{}';", spec.name, format_interval(self.config.get_refresh_rate(spec)));
                         }
                     } else {
-                        if refresh_rate.is_some() {
-                            result_str = metric.start(refresh_rate.unwrap());
-                            self.config.set_state(metric_type, MetricState::Running);
-                        } else {
-                            result_str = metric.start(self.config.get_refresh_rate(metric_type));
-                        }
+                        result_str = format!("Metric '{}' already running;", spec.name);
                     }
-
-                    self.config.set_state(metric_type, MetricState::Running);
+                } else if let Some(refresh_rate) = refresh_rate {
+                    result_str = metric.start(refresh_rate);
+                } else {
+                    result_str = metric.start(self.config.get_refresh_rate(spec));
                 }
+
+                self.config.set_state(spec, MetricState::Running);
             }
+        }
 
         result_str
     }
 
     fn stop_metric(&mut self, cli_command: CliCommand) -> String {
         let mut result_str = String::new();
-        let metric_type = cli_command.get_metric_type().unwrap();
-        let metric_state = self.config.get_state(metric_type);
+        let spec = cli_command.get_metric().unwrap();
+        let metric_state = self.config.get_state(spec);
 
         for metric in self.metrics.iter_mut() {
-            if metric.get_type() == metric_type {
+            if metric.get_spec().name == spec.name {
 
                 if metric_state == MetricState::Running {
                     result_str = metric.stop();
-                    self.config.set_state(metric_type, MetricState::Stopped);
+                    self.config.set_state(spec, MetricState::Stopped);
                 } else if metric_state == MetricState::Stopped {
-                    result_str = format!("Metric '{}' already stopped;", metric_to_str(metric_type));
+                    result_str = format!("Metric '{}' already stopped;", spec.name);
                 } else {
-                    result_str = format!("Metric '{}' disabled;", metric_to_str(metric_type));
+                    result_str = format!("Metric '{}' disabled;", spec.name);
                 }
 
             }
@@ -195,27 +224,45 @@ impl LinuxKernelMonitor {
         result_str
     }
 
-    fn metric_info(&mut self, cli_command: CliCommand) -> String {
+    /// Sends "collect now" or "reset counters" to a running metric.
+    fn control_metric(&mut self, cli_command: CliCommand) -> String {
+        let spec = cli_command.get_metric().unwrap();
+        if self.config.get_state(spec) != MetricState::Running {
+            return format!("Metric '{}' is not running;", spec.name);
+        }
+
         let mut result_str = String::new();
-        let state = cli_command.get_state();
+        for metric in self.metrics.iter_mut() {
+            if metric.get_spec().name == spec.name {
+                result_str = match cli_command.get_command() {
+                    Command::Reset => metric.reset_counters(),
+                    _ => metric.collect_now(),
+                };
+            }
+        }
 
-        if state.is_some() {
-            for metric_type in get_metric_types() {
+        result_str
+    }
 
-                let metric_state = self.config.get_state(metric_type);
-                if metric_state == state.unwrap() {
-                    result_str = result_str + format!("Metric '{}' is in state '{}' rate '{}';", metric_to_str(metric_type), state_to_str(metric_state), self.config.get_refresh_rate(metric_type)).as_str();
+    fn metric_info(&mut self, cli_command: CliCommand) -> String {
+        let mut result_str = String::new();
+
+        if let Some(state) = cli_command.get_state() {
+            for spec in get_collectors() {
+                let metric_state = self.config.get_state(spec);
+                if metric_state == state {
+                    result_str += format!("Metric '{}' is in state '{}' rate '{}';", spec.name, state_to_str(metric_state), format_interval(self.config.get_refresh_rate(spec))).as_str();
                 }
             }
 
             if result_str.is_empty() {
-                result_str = format!("No metrics in state '{}';", state_to_str(state.unwrap()));
+                result_str = format!("No metrics in state '{}';", state_to_str(state));
             }
 
         } else {
-                let metric_type = cli_command.get_metric_type().unwrap();
-                let metric_state = self.config.get_state(metric_type);
-                result_str = result_str + format!("Metric '{}' is in state '{}' rate '{}';", metric_to_str(metric_type), state_to_str(metric_state), self.config.get_refresh_rate(metric_type)).as_str();
+            let spec = cli_command.get_metric().unwrap();
+            let metric_state = self.config.get_state(spec);
+            result_str += format!("Metric '{}' is in state '{}' rate '{}';", spec.name, state_to_str(metric_state), format_interval(self.config.get_refresh_rate(spec))).as_str();
         }
 
         result_str
@@ -224,46 +271,42 @@ impl LinuxKernelMonitor {
     fn set_config(&mut self, cli_command: CliCommand) -> String {
         let mut result_str = String::new();
 
-        let metric_type = cli_command.get_metric_type().unwrap();
-        let metric_state = self.config.get_state(metric_type);
-        let refresh_rate = cli_command.get_refresh_rate();
-        let enabled = cli_command.get_enabled();
+        let spec = cli_command.get_metric().unwrap();
+        let metric_state = self.config.get_state(spec);
 
         for metric in self.metrics.iter_mut() {
-            if metric.get_type() == metric_type {
-                if enabled.is_some() {
-                    let metric_enabled = self.config.get_enabled(metric.get_type());
-                    if enabled.unwrap() != metric_enabled {
-                        self.config.set_enabled(metric_type, enabled.unwrap());
-                        result_str = result_str + format!("'{}' metric 'enabled' is set to '{}';", metric_to_str(metric_type), enabled.unwrap()).as_str();
-
-                        if metric_state == MetricState::Running && enabled.unwrap() == false {
-                            let s = metric.stop();
-                            result_str = result_str + s.as_str();
-                            self.config.set_state(metric_type, MetricState::Disabled);
-                        } else if metric_state == MetricState::Disabled && enabled.unwrap() == true {
-                            let s = metric.start(self.config.get_refresh_rate(metric.get_type()));
-                            result_str = result_str + s.as_str();
-                            self.config.set_state(metric_type, MetricState::Running);
+            if metric.get_spec().name == spec.name {
+                if let Some(enabled) = cli_command.get_enabled() {
+                    let metric_enabled = self.config.get_enabled(spec);
+                    if enabled != metric_enabled {
+                        self.config.set_enabled(spec, enabled);
+                        result_str += format!("'{}' metric 'enabled' is set to '{}';", spec.name, enabled).as_str();
+
+                        if metric_state == MetricState::Running && !enabled {
+                            result_str += metric.stop().as_str();
+                            self.config.set_state(spec, MetricState::Disabled);
+                        } else if metric_state == MetricState::Disabled && enabled {
+                            result_str += metric.start(self.config.get_refresh_rate(spec)).as_str();
+                            self.config.set_state(spec, MetricState::Running);
                         }
 
                     } else {
-                        result_str = result_str + format!("'{}' metric 'enabled' is is already '{}';", metric_to_str(metric_type), metric_enabled).as_str();
+                        result_str += format!("'{}' metric 'enabled' is is already '{}';", spec.name, metric_enabled).as_str();
                     }
                 }
 
-                if refresh_rate.is_some() {
-                    let metric_rate = self.config.get_refresh_rate(metric.get_type());
-                    if metric_rate != refresh_rate.unwrap() {
-                        self.config.set_refresh_rate(metric.get_type(), refresh_rate.unwrap());
+                if let Some(refresh_rate) = cli_command.get_refresh_rate() {
+                    let metric_rate = self.config.get_refresh_rate(spec);
+                    if metric_rate != refresh_rate {
+                        self.config.set_refresh_rate(spec, refresh_rate);
 
                         if metric_state == MetricState::Running {
-                            metric.set_refresh_rate(refresh_rate.unwrap());
+                            metric.set_refresh_rate(refresh_rate);
                         }
 
-                        result_str = result_str + format!("'{}' metric 'rate' is set to '{}';", metric_to_str(metric.get_type()), refresh_rate.unwrap()).as_str();
+                        result_str += format!("'{}' metric 'rate' is set to '{}';", spec.name, format_interval(refresh_rate)).as_str();
                     } else {
-                        result_str = result_str + format!("'{}' metric 'rate' is is already '{}';", metric_to_str(metric_type), metric_rate).as_str();
+                        result_str += format!("'{}' metric 'rate' is is already '{}';", spec.name, format_interval(metric_rate)).as_str();
                     }
                 }
             }
@@ -273,31 +316,22 @@ impl LinuxKernelMonitor {
     }
 
     fn store_config(&mut self) -> String {
-        let mut result_str = String::new();
-
         let config_path = Path::new(CONFIG_PATH);
         if config_path.exists() {
-            let res = File::create(config_path);
-            if res.is_err() {
-                result_str = format!("Error: failed to create config file: {:?};", res.unwrap_err());
-                return result_str;
+            if let Err(e) = File::create(config_path) {
+                return format!("Error: failed to create config file: {:?};", e);
             }
         }
 
-        let new_toml_string = toml::to_string(&self.config.get_config());
-        if new_toml_string.is_err() {
-            result_str = format!("Error: failed to serialize config file: {:?};", new_toml_string.unwrap_err());
-            return result_str;
-        }
+        let new_toml_string = match toml::to_string(&self.config.get_config()) {
+            Ok(new_toml_string) => new_toml_string,
+            Err(e) => return format!("Error: failed to serialize config file: {:?};", e),
+        };
 
-        let res = fs::write(config_path, new_toml_string.unwrap());
-        if res.is_err() {
-            result_str = format!("Error: failed to write config file: {:?};", res.unwrap_err());
-            return result_str;
+        if let Err(e) = fs::write(config_path, new_toml_string) {
+            return format!("Error: failed to write config file: {:?};", e);
         }
 
-        result_str = String::from("Config successfully saved");
-
-        result_str
+        String::from("Config successfully saved")
     }
-}
\ No newline at end of file
+}
@@ -1,7 +1,7 @@
 use std::sync::{Arc, Mutex};
 use std::thread::JoinHandle;
 
-use crate::queue::{Queue, QueueItem, ErrInfo};
+use crate::queue::{Queue, QueueItem};
 
 pub struct Logger {
     log_collector: Arc<Mutex<LogCollector>>,
@@ -38,9 +38,9 @@ impl LogCollector {
         loop {
             if let Some(item) = self.queue.dequeue() {
                 match item {
-                    QueueItem::CPU(cpu_info) => println!("CPU Info:\n{}", cpu_info),
-                    QueueItem::Memory(mem_info) => println!("Memory Info:\n{}", mem_info),
-                    QueueItem::IO(io_info) => println!("I/O Info:\n{}", io_info),
+                    QueueItem::Sample(sample) => println!("{}", sample),
+                    QueueItem::KernelEvent(kmsg_event) => println!("Kernel Event: {}", kmsg_event),
+                    QueueItem::Event(event_info) => println!("{}", event_info),
                     QueueItem::Err(err_info) => println!("{}", err_info),
                 }
             }
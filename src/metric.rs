@@ -1,24 +1,88 @@
-use std::thread::{sleep, JoinHandle};
-use std::time::Duration;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime};
 use std::sync::{Arc, Mutex};
 use std::thread;
-use crossbeam_channel::{Receiver, Sender, TryRecvError};
-use crate::metric::cpu::CpuInfoCollector;
-use crate::metric::io::IOInfoCollector;
-use crate::metric::memory::MemoryInfoCollector;
+use crossbeam_channel::{Receiver, RecvTimeoutError, Sender};
+use crate::config::Options;
+use crate::interval::{format_interval, until_next_tick};
+use crate::metric::host::HostPaths;
 use crate::queue::{Queue, QueueItem};
 
 pub mod memory;
 pub mod cpu;
+pub mod host;
 pub mod io;
+pub mod network;
+pub mod pressure;
+pub mod process;
+pub mod cgroup;
+pub mod scheduler;
+pub mod sensors;
+pub mod kmsg;
+pub mod vmstat;
+pub mod netstack;
+pub mod interrupts;
+pub mod limits;
+pub mod storage;
+pub mod numa;
+pub mod hwerror;
+pub mod kernel;
+
+
+/// Collectors the monitor runs, in the order they are started and listed. A new
+/// collector is a module defining its `COLLECTOR` entry, added here.
+static COLLECTORS: [&CollectorSpec; 18] = [
+    &cpu::COLLECTOR,
+    &memory::COLLECTOR,
+    &io::COLLECTOR,
+    &network::COLLECTOR,
+    &pressure::COLLECTOR,
+    &process::COLLECTOR,
+    &cgroup::COLLECTOR,
+    &scheduler::COLLECTOR,
+    &sensors::COLLECTOR,
+    &kmsg::COLLECTOR,
+    &vmstat::COLLECTOR,
+    &netstack::COLLECTOR,
+    &interrupts::COLLECTOR,
+    &limits::COLLECTOR,
+    &storage::COLLECTOR,
+    &numa::COLLECTOR,
+    &hwerror::COLLECTOR,
+    &kernel::COLLECTOR,
+];
+
+/// Builds a collector from its config section, fails on invalid options.
+pub type CollectorConstructor = fn(&Options, &HostPaths) -> Result<Box<dyn MetricCollector>, String>;
+
+/// Registry entry of a collector.
+#[derive(Debug)]
+pub struct CollectorSpec {
+    /// Name used by lkmonitorctl and in events, e.g. "cpu".
+    pub name: &'static str,
+    /// Section of the config file holding the collector options, e.g. "cpu_config".
+    pub section: &'static str,
+    /// Heading the collector output is logged under.
+    pub title: &'static str,
+    /// Collection interval used when the config doesn't set one.
+    pub refresh_rate: Duration,
+    /// Collector specific options with their default values.
+    pub default_options: fn() -> Options,
+    pub new: CollectorConstructor,
+}
 
+/// Longest time an event driven collector waits for events before the control
+/// channel is checked again.
+const EVENT_WAIT_SLICE: Duration = Duration::from_millis(100);
 
-#[derive(Clone, Copy, Eq, PartialEq, Debug, Hash)]
-pub enum MetricType {
-    CPU,
-    Memory,
-    IO,
-    None,
+/// Commands sent to a running collector thread.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum ControlCommand {
+    Stop,
+    SetRate(Duration),
+    /// Collect right away, the following collections stay on the ticks of the rate.
+    CollectNow,
+    ResetCounters,
 }
 
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
@@ -30,19 +94,19 @@ pub enum MetricState {
 }
 
 pub struct Metric {
-    metric: Arc<Mutex<dyn MetricCollector>>,
-    metric_type: MetricType,
+    metric: Arc<Mutex<Box<dyn MetricCollector>>>,
+    spec: &'static CollectorSpec,
     queue: Arc<Queue>,
-    sender: Arc<Sender<String>>,
-    receiver: Arc<Receiver<String>>,
+    sender: Arc<Sender<ControlCommand>>,
+    receiver: Arc<Receiver<ControlCommand>>,
     handle: Option<JoinHandle<()>>,
 }
 
 impl Metric {
-    pub fn new(metric: Arc<Mutex<dyn MetricCollector>>, metric_type: MetricType, queue: Arc<Queue>, sender: Arc<Sender<String>>, receiver: Arc<Receiver<String>>, handle: Option<JoinHandle<()>>) -> Self {
+    pub fn new(metric: Box<dyn MetricCollector>, spec: &'static CollectorSpec, queue: Arc<Queue>, sender: Arc<Sender<ControlCommand>>, receiver: Arc<Receiver<ControlCommand>>, handle: Option<JoinHandle<()>>) -> Self {
         Self {
-            metric,
-            metric_type,
+            metric: Arc::new(Mutex::new(metric)),
+            spec,
             queue,
             sender,
             receiver,
@@ -50,19 +114,19 @@ impl Metric {
         }
     }
 
-    pub fn get_type(&self) -> MetricType {
-        self.metric_type.clone()
+    pub fn get_spec(&self) -> &'static CollectorSpec {
+        self.spec
     }
 
     pub fn get_queue(&self) -> Arc<Queue> {
         self.queue.clone()
     }
 
-    pub fn get_metric_collector(&self) -> Arc<Mutex<dyn MetricCollector>> {
+    pub fn get_metric_collector(&self) -> Arc<Mutex<Box<dyn MetricCollector>>> {
         self.metric.clone()
     }
 
-    pub fn get_receiver(&self) -> Arc<Receiver<String>> {
+    pub fn get_receiver(&self) -> Arc<Receiver<ControlCommand>> {
         self.receiver.clone()
     }
 
@@ -71,19 +135,19 @@ impl Metric {
     }
 
     pub fn stop(&mut self) -> String {
-        self.send_command("stop".to_string());
+        self.send_command(ControlCommand::Stop);
 
         let handle = self.handle.take().unwrap();
         let join_result = handle.join();
 
         if join_result.is_ok() {
-           format!("Metric '{}' stopped;", metric_to_str(self.get_type()))
+           format!("Metric '{}' stopped;", self.spec.name)
         } else {
             String::from("Error while joining thread;")
         }
     }
 
-    pub fn start(&mut self, refresh_rate: u8) -> String {
+    pub fn start(&mut self, refresh_rate: Duration) -> String {
         let metric_collector = self.get_metric_collector();
         let rx = self.get_receiver();
         let queue = self.get_queue();
@@ -95,89 +159,99 @@ impl Metric {
 
         self.add_handle(handle);
 
-        format!("Metric '{}' started with rate '{}';", metric_to_str(self.get_type()), refresh_rate.to_string())
+        format!("Metric '{}' started with rate '{}';", self.spec.name, format_interval(refresh_rate))
+    }
+
+    pub fn set_refresh_rate(&mut self, refresh_rate: Duration) {
+        self.send_command(ControlCommand::SetRate(refresh_rate));
+    }
+
+    pub fn collect_now(&mut self) -> String {
+        self.send_command(ControlCommand::CollectNow);
+        format!("Metric '{}' collecting;", self.spec.name)
     }
 
-    pub fn set_refresh_rate(&mut self, refresh_rate: u8) {
-        self.send_command(refresh_rate.to_string());
+    pub fn reset_counters(&mut self) -> String {
+        self.send_command(ControlCommand::ResetCounters);
+        format!("Metric '{}' counters reset;", self.spec.name)
     }
 
-    fn send_command(&mut self, command: String) {
+    fn send_command(&mut self, command: ControlCommand) {
+        // the receiver is owned by the metric as well, so sending can't fail
         self.sender.send(command).unwrap();
     }
 }
 
 pub trait MetricCollector: Send + Sync {
-    fn run (&mut self, receiver: Arc<Receiver<String>>, refresh_rate: u8, queue: Arc<Queue>) {
+    /// Collects right away, then on every tick of the refresh rate aligned to
+    /// the wall clock (see `until_next_tick`). Between collections it waits on
+    /// the control channel, so commands take effect as soon as they are sent.
+    fn run (&mut self, receiver: Arc<Receiver<ControlCommand>>, refresh_rate: Duration, queue: Arc<Queue>) {
         let mut refresh_rate = refresh_rate;
 
         loop {
-            let queue_item = self.collect_info();
-            match queue_item {
-                QueueItem::CPU(cpu_info) => {
-                    queue.enqueue(QueueItem::CPU(cpu_info))
-                },
-                QueueItem::Memory(mem_info) => {
-                    queue.enqueue(QueueItem::Memory(mem_info))
-                },
-                QueueItem::IO(io_info) => {
-                    queue.enqueue(QueueItem::IO(io_info))
-                },
-                QueueItem::Err(err_info) => {
-                    queue.enqueue(QueueItem::Err(err_info))
-                },
+            queue.enqueue(self.collect_info());
+            for queue_item in self.collect_events() {
+                queue.enqueue(queue_item);
             }
 
-            sleep(Duration::from_secs(refresh_rate as u64));
-
-            match receiver.try_recv() {
-                Ok(command) => {
-                    if  command == "stop" {
-                        break;
-                    } else {
-                        refresh_rate = command.parse::<u8>().unwrap();
+            let mut deadline = Instant::now() + until_next_tick(SystemTime::now(), refresh_rate);
+            let mut now = Instant::now();
+            while now < deadline {
+                let mut timeout = deadline - now;
+                if self.is_event_driven() {
+                    for queue_item in self.wait(timeout.min(EVENT_WAIT_SLICE)) {
+                        queue.enqueue(queue_item);
                     }
-                },
-                Err(TryRecvError::Empty) => (),
-                Err(TryRecvError::Disconnected) => eprintln!("disconnected"),
+                    timeout = Duration::ZERO;
+                }
+
+                match receiver.recv_timeout(timeout) {
+                    Ok(ControlCommand::Stop) => return,
+                    Ok(ControlCommand::SetRate(rate)) => {
+                        refresh_rate = rate;
+                        deadline = Instant::now() + until_next_tick(SystemTime::now(), refresh_rate);
+                    },
+                    Ok(ControlCommand::CollectNow) => break,
+                    Ok(ControlCommand::ResetCounters) => self.reset_counters(),
+                    Err(RecvTimeoutError::Timeout) => (),
+                    Err(RecvTimeoutError::Disconnected) => return,
+                }
+                now = Instant::now();
             }
         }
     }
 
     fn collect_info(&mut self) -> QueueItem;
-}
 
-pub fn get_metric_collector(metric_type: MetricType) -> Option<Arc<Mutex<dyn MetricCollector>>> {
-    match metric_type {
-        MetricType::CPU => Some(Arc::new(Mutex::new(CpuInfoCollector::new()))),
-        MetricType::Memory => Some(Arc::new(Mutex::new(MemoryInfoCollector::new()))),
-        MetricType::IO => Some(Arc::new(Mutex::new(IOInfoCollector::new()))),
-        MetricType::None => None,
+    /// Events detected by the last `collect_info` call, e.g. state changes.
+    fn collect_events(&mut self) -> Vec<QueueItem> {
+        Vec::new()
     }
-}
 
+    /// Whether the collector overrides `wait` to watch event sources between
+    /// collections.
+    fn is_event_driven(&self) -> bool {
+        false
+    }
 
+    /// Waits up to `timeout` for events of an event driven collector and
+    /// returns them as soon as they happen.
+    fn wait(&mut self, _timeout: Duration) -> Vec<QueueItem> {
+        Vec::new()
+    }
 
-pub fn get_metric_types() -> Vec<MetricType> {
-    vec![MetricType::CPU, MetricType::Memory, MetricType::IO]
+    /// Clears the counters the collector accumulates and the previous readings
+    /// rates are computed from, the next collection starts over like the first one.
+    fn reset_counters(&mut self) {}
 }
 
-pub fn metric_to_str(metric_type: MetricType) -> &'static str {
-    match metric_type {
-        MetricType::CPU => "cpu",
-        MetricType::Memory => "memory",
-        MetricType::IO => "io",
-        MetricType::None => "none",
-    }
+pub fn get_collectors() -> &'static [&'static CollectorSpec] {
+    &COLLECTORS
 }
 
-pub fn str_to_metric(metric_str: &str) -> MetricType {
-    match metric_str {
-        "cpu" => MetricType::CPU,
-        "memory" => MetricType::Memory,
-        "io" => MetricType::IO,
-        _ => MetricType::None,
-    }
+pub fn find_collector(name: &str) -> Option<&'static CollectorSpec> {
+    COLLECTORS.iter().copied().find(|spec| spec.name == name)
 }
 
 pub fn state_to_str(state: MetricState) -> &'static str {
@@ -197,4 +271,78 @@ pub fn str_to_state(metric_str: &str) -> Option<MetricState> {
         "disabled" => Some(MetricState::Disabled),
         _ => None,
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::MetricsConfig;
+    use crate::metric::host::fixture_paths;
+
+    #[test]
+    fn registry_names_are_unique() {
+        for (i, spec) in COLLECTORS.iter().enumerate() {
+            assert!(COLLECTORS[i + 1..].iter().all(|other| other.name != spec.name && other.section != spec.section), "{}", spec.name);
+            assert_eq!(find_collector(spec.name).unwrap().section, spec.section);
+        }
+        assert!(find_collector("none").is_none());
+    }
+
+    #[test]
+    fn collectors_build_from_default_config() {
+        let config = MetricsConfig::default();
+        for spec in get_collectors() {
+            assert!((spec.new)(&config.get_options(spec), &fixture_paths("kvm-guest/t0")).is_ok(), "{}", spec.name);
+        }
+    }
+
+    #[test]
+    fn invalid_options_are_rejected() {
+        let mut options = MetricsConfig::default().get_options(&io::COLLECTOR);
+        options.insert(String::from("exclude_devices"), toml::Value::String(String::from("loop")));
+        assert!((io::COLLECTOR.new)(&options, &fixture_paths("kvm-guest/t0")).is_err());
+    }
+
+    /// Reports how many times it collected since the last reset.
+    struct CountingCollector {
+        collections: u64,
+    }
+
+    impl MetricCollector for CountingCollector {
+        fn collect_info(&mut self) -> QueueItem {
+            self.collections += 1;
+            QueueItem::Err(crate::queue::ErrInfo::new(self.collections.to_string()))
+        }
+
+        fn reset_counters(&mut self) {
+            self.collections = 0;
+        }
+    }
+
+    #[test]
+    fn commands_interrupt_the_wait() {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        let queue = Arc::new(Queue::new());
+        let thread_queue = queue.clone();
+        let started = Instant::now();
+        let handle = thread::spawn(move || {
+            let mut collector = CountingCollector { collections: 0 };
+            collector.run(Arc::new(receiver), Duration::from_secs(3600), thread_queue);
+            collector.collections
+        });
+
+        for command in [ControlCommand::CollectNow, ControlCommand::ResetCounters, ControlCommand::CollectNow, ControlCommand::Stop] {
+            sender.send(command).unwrap();
+        }
+        // collected on start and twice on request, the reset came in between
+        assert_eq!(handle.join().unwrap(), 1);
+        assert!(started.elapsed() < Duration::from_secs(5));
+        let collections: Vec<String> = std::iter::from_fn(|| (!queue.is_empty()).then(|| queue.dequeue()).flatten())
+            .map(|item| match item {
+                QueueItem::Err(err_info) => err_info.to_string(),
+                _ => String::new(),
+            })
+            .collect();
+        assert_eq!(collections, ["ErrInfo: 1\n", "ErrInfo: 2\n", "ErrInfo: 1\n"]);
+    }
+}
@@ -0,0 +1,406 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::{from_options, to_options};
+use crate::queue::{ErrInfo, QueueItem};
+use crate::metric::{CollectorSpec, MetricCollector};
+use crate::metric::host::{elapsed_secs, HostPaths};
+use crate::sample::{Point, SampleSource, Unit};
+
+
+pub struct CgroupInfo {
+    cgroups_info: Vec<CgroupUsage>,
+}
+
+/// Resource usage of a single cgroup between two samples.
+pub struct CgroupUsage {
+    name: String,
+    cpu_usage: f64,
+    cpu_throttled: f64,
+    nr_throttled: u64,
+    memory_current: u64,
+    memory_max: Option<u64>,
+    oom: u64,
+    oom_kill: u64,
+    read_bytes_per_sec: f64,
+    write_bytes_per_sec: f64,
+    read_ios_per_sec: f64,
+    write_ios_per_sec: f64,
+    pids_current: u64,
+}
+
+impl Display for CgroupInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for cgroup_info in &self.cgroups_info {
+            writeln!(f, "{}", cgroup_info)?;
+        }
+        Ok(())
+    }
+}
+
+impl SampleSource for CgroupInfo {
+    fn points(&self) -> Vec<Point> {
+        let mut points = Vec::new();
+        for cgroup in &self.cgroups_info {
+            let mut values = vec![
+                Point::gauge("cgroup_cpu", Unit::Percent, cgroup.cpu_usage),
+                Point::gauge("cgroup_cpu_throttled", Unit::Percent, cgroup.cpu_throttled),
+                Point::counter("cgroup_throttled_periods", Unit::Count, cgroup.nr_throttled as f64),
+                Point::gauge("cgroup_memory", Unit::Bytes, cgroup.memory_current as f64),
+                Point::counter("cgroup_oom", Unit::Count, cgroup.oom as f64),
+                Point::counter("cgroup_oom_kill", Unit::Count, cgroup.oom_kill as f64),
+                Point::rate("cgroup_read_bytes", Unit::Bytes, cgroup.read_bytes_per_sec),
+                Point::rate("cgroup_write_bytes", Unit::Bytes, cgroup.write_bytes_per_sec),
+                Point::rate("cgroup_read_ios", Unit::Count, cgroup.read_ios_per_sec),
+                Point::rate("cgroup_write_ios", Unit::Count, cgroup.write_ios_per_sec),
+                Point::gauge("cgroup_pids", Unit::Count, cgroup.pids_current as f64),
+            ];
+            if let Some(memory_max) = cgroup.memory_max {
+                values.push(Point::gauge("cgroup_memory_max", Unit::Bytes, memory_max as f64));
+            }
+            points.extend(values.into_iter().map(|point| point.label("cgroup", &cgroup.name)));
+        }
+        points
+    }
+}
+
+impl Display for CgroupUsage {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let memory_max = match self.memory_max {
+            Some(memory_max) => format!("{} MB", memory_max / (1024 * 1024)),
+            None => String::from("max"),
+        };
+        write!(f, "cgroup: {}, cpu: {:.2}%, throttled: {:.2}% ({} periods), memory: {} MB of {}, oom: {}, oom_kill: {}, read: {:.2} kB/s {:.2} io/s, write: {:.2} kB/s {:.2} io/s, pids: {}",
+               self.name, self.cpu_usage, self.cpu_throttled, self.nr_throttled, self.memory_current / (1024 * 1024), memory_max,
+               self.oom, self.oom_kill, self.read_bytes_per_sec / 1024.0, self.read_ios_per_sec,
+               self.write_bytes_per_sec / 1024.0, self.write_ios_per_sec, self.pids_current)
+    }
+}
+
+/// Counters read from the cgroup v2 interface files.
+#[derive(Clone, Copy, Default, Debug)]
+struct CgroupStats {
+    usage_usec: u64,
+    throttled_usec: u64,
+    nr_throttled: u64,
+    memory_current: u64,
+    memory_max: Option<u64>,
+    oom: u64,
+    oom_kill: u64,
+    read_bytes: u64,
+    write_bytes: u64,
+    read_ios: u64,
+    write_ios: u64,
+    pids_current: u64,
+}
+
+impl CgroupStats {
+    /// Controllers not enabled for the cgroup have no interface files and are reported as zero.
+    fn read(path: &Path) -> Self {
+        let mut stats = CgroupStats::default();
+
+        for (key, value) in read_flat_keyed(&path.join("cpu.stat")) {
+            match key.as_str() {
+                "usage_usec" => stats.usage_usec = value,
+                "throttled_usec" => stats.throttled_usec = value,
+                "nr_throttled" => stats.nr_throttled = value,
+                _ => (),
+            }
+        }
+
+        for (key, value) in read_flat_keyed(&path.join("memory.events")) {
+            match key.as_str() {
+                "oom" => stats.oom = value,
+                "oom_kill" => stats.oom_kill = value,
+                _ => (),
+            }
+        }
+
+        stats.memory_current = read_value(&path.join("memory.current")).unwrap_or(0);
+        // "max" means no limit
+        stats.memory_max = read_value(&path.join("memory.max"));
+        stats.pids_current = read_value(&path.join("pids.current")).unwrap_or(0);
+
+        // io.stat has one line per device: "8:0 rbytes=.. wbytes=.. rios=.. wios=.. dbytes=.. dios=.."
+        if let Ok(io_stat) = fs::read_to_string(path.join("io.stat")) {
+            for field in io_stat.split_whitespace() {
+                if let Some((key, value)) = field.split_once('=') {
+                    let value = value.parse::<u64>().unwrap_or(0);
+                    match key {
+                        "rbytes" => stats.read_bytes += value,
+                        "wbytes" => stats.write_bytes += value,
+                        "rios" => stats.read_ios += value,
+                        "wios" => stats.write_ios += value,
+                        _ => (),
+                    }
+                }
+            }
+        }
+
+        stats
+    }
+}
+
+fn read_flat_keyed(path: &Path) -> Vec<(String, u64)> {
+    let content = fs::read_to_string(path).unwrap_or_default();
+    content
+        .lines()
+        .filter_map(|line| {
+            let (key, value) = line.split_once(' ')?;
+            Some((key.to_string(), value.trim().parse::<u64>().ok()?))
+        })
+        .collect()
+}
+
+fn read_value(path: &Path) -> Option<u64> {
+    fs::read_to_string(path).ok()?.trim().parse::<u64>().ok()
+}
+
+impl CgroupUsage {
+    fn new(name: &str, prev: Option<&CgroupStats>, curr: &CgroupStats, elapsed_secs: f64) -> Self {
+        let rate = |prev: u64, curr: u64| {
+            if elapsed_secs > 0.0 {
+                curr.saturating_sub(prev) as f64 / elapsed_secs
+            } else {
+                0.0
+            }
+        };
+        let prev = prev.copied().unwrap_or(*curr);
+
+        CgroupUsage {
+            name: name.to_string(),
+            // usec of cpu time per second of wall time, 100% is one full cpu
+            cpu_usage: rate(prev.usage_usec, curr.usage_usec) / 10_000.0,
+            cpu_throttled: rate(prev.throttled_usec, curr.throttled_usec) / 10_000.0,
+            nr_throttled: curr.nr_throttled,
+            memory_current: curr.memory_current,
+            memory_max: curr.memory_max,
+            oom: curr.oom,
+            oom_kill: curr.oom_kill,
+            read_bytes_per_sec: rate(prev.read_bytes, curr.read_bytes),
+            write_bytes_per_sec: rate(prev.write_bytes, curr.write_bytes),
+            read_ios_per_sec: rate(prev.read_ios, curr.read_ios),
+            write_ios_per_sec: rate(prev.write_ios, curr.write_ios),
+            pids_current: curr.pids_current,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(default)]
+pub struct CgroupConfig {
+    root: String,
+    max_depth: usize,
+    slices: Vec<String>,
+}
+
+impl Default for CgroupConfig {
+    fn default() -> Self {
+        CgroupConfig {
+            root: String::from("/sys/fs/cgroup"),
+            max_depth: 2,
+            slices: Vec::new(),
+        }
+    }
+}
+
+impl CgroupConfig {
+    /// Mount point of the unified cgroup v2 hierarchy.
+    pub fn get_root(&self) -> &str {
+        &self.root
+    }
+
+    /// How many levels below the root (or below each slice) are reported.
+    pub fn get_max_depth(&self) -> usize {
+        self.max_depth
+    }
+
+    /// Cgroups relative to the root (e.g. `system.slice`), all cgroups are walked when empty.
+    pub fn get_slices(&self) -> &Vec<String> {
+        &self.slices
+    }
+}
+
+pub static COLLECTOR: CollectorSpec = CollectorSpec {
+    name: "cgroup",
+    section: "cgroup_config",
+    title: "Cgroup Info",
+    refresh_rate: Duration::from_secs(5),
+    default_options: || to_options(&CgroupConfig::default()),
+    new: |options, paths| Ok(Box::new(CgroupInfoCollector::new(&from_options(options)?, paths))),
+};
+
+pub struct CgroupInfoCollector {
+    root: PathBuf,
+    max_depth: usize,
+    slices: Vec<String>,
+    prev_stats: HashMap<String, CgroupStats>,
+    prev_time: Option<f64>,
+    paths: HostPaths,
+}
+
+impl MetricCollector for CgroupInfoCollector {
+    fn collect_info(&mut self) -> QueueItem {
+        match self.get_cgroup_info() {
+            Ok(cgroup_info) => QueueItem::sample(&COLLECTOR, &cgroup_info),
+            Err(e) => QueueItem::Err(ErrInfo::new(format!("cgroup: failed to read {}: {}", self.root.display(), e))),
+        }
+    }
+
+    fn reset_counters(&mut self) {
+        self.prev_stats.clear();
+        self.prev_time = None;
+    }
+}
+
+impl CgroupInfoCollector {
+    pub fn new(config: &CgroupConfig, paths: &HostPaths) -> Self {
+        Self {
+            root: paths.resolve(config.get_root()),
+            max_depth: config.get_max_depth(),
+            slices: config.get_slices().clone(),
+            prev_stats: HashMap::new(),
+            prev_time: None,
+            paths: paths.clone(),
+        }
+    }
+
+    /// Walks the unified hierarchy and computes rates since the previous call.
+    /// Rates of cgroups seen for the first time are reported as zero.
+    pub fn get_cgroup_info(&mut self) -> Result<CgroupInfo, std::io::Error> {
+        // cgroup.controllers only exists on the unified (v2) hierarchy
+        fs::metadata(self.root.join("cgroup.controllers"))?;
+
+        let now = self.paths.read_uptime();
+        let elapsed_secs = elapsed_secs(self.prev_time, now);
+
+        let mut paths = Vec::new();
+        if self.slices.is_empty() {
+            self.walk(&self.root, 1, &mut paths);
+        } else {
+            for slice in &self.slices {
+                let path = self.root.join(slice.trim_start_matches('/'));
+                if path.is_dir() {
+                    paths.push(path.clone());
+                    self.walk(&path, 1, &mut paths);
+                }
+            }
+        }
+
+        let mut stats_map = HashMap::new();
+        let mut cgroups_info = Vec::new();
+        for path in paths {
+            let name = format!("/{}", path.strip_prefix(&self.root).unwrap_or(&path).display());
+            let stats = CgroupStats::read(&path);
+            cgroups_info.push(CgroupUsage::new(&name, self.prev_stats.get(&name), &stats, elapsed_secs));
+            stats_map.insert(name, stats);
+        }
+
+        self.prev_stats = stats_map;
+        self.prev_time = now;
+
+        Ok(CgroupInfo { cgroups_info })
+    }
+
+    /// Collects child cgroups of `path`, which are `depth` levels below the starting cgroup.
+    fn walk(&self, path: &Path, depth: usize, paths: &mut Vec<PathBuf>) {
+        if depth > self.max_depth {
+            return;
+        }
+
+        let entries = match fs::read_dir(path) {
+            Ok(entries) => entries,
+            Err(_) => return,
+        };
+
+        let mut children: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_type().map(|t| t.is_dir()).unwrap_or(false))
+            .map(|entry| entry.path())
+            .collect();
+        children.sort();
+
+        for child in children {
+            paths.push(child.clone());
+            self.walk(&child, depth + 1, paths);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metric::host::{assert_close, fixture_paths};
+    use crate::sample::find_point;
+
+    fn collect_t1(config: &CgroupConfig) -> CgroupInfo {
+        let mut collector = CgroupInfoCollector::new(config, &fixture_paths("scenarios/bare-metal/t0"));
+        collector.get_cgroup_info().unwrap();
+        collector.paths = fixture_paths("scenarios/bare-metal/t1");
+        collector.root = collector.paths.resolve(config.get_root());
+        collector.get_cgroup_info().unwrap()
+    }
+
+    fn names(cgroup_info: &CgroupInfo) -> Vec<&str> {
+        cgroup_info.cgroups_info.iter().map(|cgroup| cgroup.name.as_str()).collect()
+    }
+
+    #[test]
+    fn unit_rates_between_snapshots() {
+        let cgroup_info = collect_t1(&CgroupConfig::default());
+        assert_eq!(names(&cgroup_info), [
+            "/system.slice",
+            "/system.slice/nginx.service",
+            "/system.slice/postgresql.service",
+            "/user.slice",
+        ]);
+
+        // 2.5 s between the snapshots
+        let postgresql = &cgroup_info.cgroups_info[2];
+        assert_close(postgresql.cpu_usage, 1_500_000.0 / 2.5 / 10_000.0);
+        assert_close(postgresql.cpu_throttled, 0.0);
+        // summed over both devices
+        assert_close(postgresql.read_bytes_per_sec, (2621440.0 + 262144.0) / 2.5);
+        assert_close(postgresql.write_bytes_per_sec, 5242880.0 / 2.5);
+        assert_close(postgresql.read_ios_per_sec, (40.0 + 4.0) / 2.5);
+        assert_close(postgresql.write_ios_per_sec, 100.0 / 2.5);
+        assert_eq!(postgresql.memory_current, 633339904);
+        assert_eq!(postgresql.memory_max, None);
+        assert_eq!((postgresql.oom, postgresql.oom_kill, postgresql.pids_current), (1, 1, 12));
+
+        let nginx = &cgroup_info.cgroups_info[1];
+        assert_close(nginx.cpu_usage, 250_000.0 / 2.5 / 10_000.0);
+        assert_close(nginx.cpu_throttled, 500_000.0 / 2.5 / 10_000.0);
+        // periods throttled since the cgroup was created
+        assert_eq!(nginx.nr_throttled, 9);
+        assert_eq!(nginx.memory_max, Some(536870912));
+        assert_close(nginx.read_bytes_per_sec, 0.0);
+
+        let points = cgroup_info.points();
+        let cpu = find_point(&points, "cgroup_cpu", &[("cgroup", "/system.slice")]).unwrap();
+        assert_close(cpu.get_value(), 1_800_000.0 / 2.5 / 10_000.0);
+        assert!(find_point(&points, "cgroup_memory_max", &[("cgroup", "/user.slice")]).is_none());
+        let throttled = find_point(&points, "cgroup_throttled_periods", &[("cgroup", "/system.slice/nginx.service")]).unwrap();
+        assert_eq!(throttled.get_value(), 9.0);
+    }
+
+    #[test]
+    fn slices_and_depth() {
+        let config = CgroupConfig { max_depth: 0, slices: vec![String::from("/system.slice")], ..CgroupConfig::default() };
+        assert_eq!(names(&collect_t1(&config)), ["/system.slice"]);
+
+        let config = CgroupConfig { max_depth: 1, slices: vec![String::from("system.slice")], ..CgroupConfig::default() };
+        assert_eq!(names(&collect_t1(&config)), ["/system.slice", "/system.slice/nginx.service", "/system.slice/postgresql.service"]);
+    }
+
+    #[test]
+    fn cgroup_v1_is_an_error() {
+        let mut collector = CgroupInfoCollector::new(&CgroupConfig::default(), &fixture_paths("kvm-guest/t0"));
+        assert!(collector.get_cgroup_info().is_err());
+    }
+}
@@ -1,11 +1,20 @@
+use std::collections::HashMap;
 use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
 
-use sysinfo::System;
+use crate::metric::{CollectorSpec, MetricCollector};
+use crate::metric::host::{elapsed_secs, HostPaths};
+use crate::queue::{ErrInfo, QueueItem};
+use crate::sample::{Point, SampleSource, Unit};
 
-use crate::metric::MetricCollector;
-use crate::queue::QueueItem;
 
+const PROC_STAT_PATH: &str = "/proc/stat";
+const CPU_SYSFS_PATH: &str = "/sys/devices/system/cpu";
 
+/// Raw jiffies counters of a single `cpu` line from /proc/stat.
+#[derive(Clone, Copy, Default, Debug)]
 struct CpuStats {
     user: u64,
     nice: u64,
@@ -20,46 +29,437 @@ struct CpuStats {
     total: u64,
 }
 
+impl CpuStats {
+    fn parse(line: &str) -> Option<(String, CpuStats)> {
+        let mut fields = line.split_whitespace();
+        let name = fields.next()?;
+        if !name.starts_with("cpu") {
+            return None;
+        }
+
+        let values: Vec<u64> = fields.map(|v| v.parse::<u64>().unwrap_or(0)).collect();
+        let value = |idx: usize| values.get(idx).copied().unwrap_or(0);
+
+        let mut stats = CpuStats {
+            user: value(0),
+            nice: value(1),
+            system: value(2),
+            idle: value(3),
+            iowait: value(4),
+            irq: value(5),
+            softirq: value(6),
+            steal: value(7),
+            guest: value(8),
+            guest_nice: value(9),
+            total: 0,
+        };
+        // guest and guest_nice are already accounted in user and nice
+        stats.total = stats.user + stats.nice + stats.system + stats.idle + stats.iowait
+            + stats.irq + stats.softirq + stats.steal;
+
+        Some((name.to_string(), stats))
+    }
+}
+
+/// Share of time spent in every /proc/stat state between two samples, in percent.
+#[derive(Clone, Debug, Default)]
+pub struct CpuUsage {
+    name: String,
+    user: f32,
+    nice: f32,
+    system: f32,
+    idle: f32,
+    iowait: f32,
+    irq: f32,
+    softirq: f32,
+    steal: f32,
+    guest: f32,
+    guest_nice: f32,
+}
+
+impl CpuUsage {
+    fn new(name: &str, prev: &CpuStats, curr: &CpuStats) -> Self {
+        let total = curr.total.saturating_sub(prev.total);
+        let percent = |prev: u64, curr: u64| {
+            if total == 0 {
+                0.0
+            } else {
+                (curr.saturating_sub(prev) as f64 * 100.0 / total as f64) as f32
+            }
+        };
+
+        CpuUsage {
+            name: name.to_string(),
+            user: percent(prev.user, curr.user),
+            nice: percent(prev.nice, curr.nice),
+            system: percent(prev.system, curr.system),
+            idle: percent(prev.idle, curr.idle),
+            iowait: percent(prev.iowait, curr.iowait),
+            irq: percent(prev.irq, curr.irq),
+            softirq: percent(prev.softirq, curr.softirq),
+            steal: percent(prev.steal, curr.steal),
+            guest: percent(prev.guest, curr.guest),
+            guest_nice: percent(prev.guest_nice, curr.guest_nice),
+        }
+    }
+
+    /// Time not spent idle or waiting for I/O.
+    pub fn get_load(&self) -> f32 {
+        (100.0 - self.idle - self.iowait).max(0.0)
+    }
+}
+
+impl Display for CpuUsage {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, " {}: Load: {:.2}%, user: {:.2}%, nice: {:.2}%, system: {:.2}%, idle: {:.2}%, iowait: {:.2}%, irq: {:.2}%, softirq: {:.2}%, steal: {:.2}%, guest: {:.2}%, guest_nice: {:.2}%",
+               self.name, self.get_load(), self.user, self.nice, self.system, self.idle, self.iowait,
+               self.irq, self.softirq, self.steal, self.guest, self.guest_nice)
+    }
+}
+
+/// Frequency scaling state of a core from cpufreq, in kHz.
+#[derive(Clone, Debug, Default)]
+pub struct CpuFreq {
+    cur_khz: u64,
+    min_khz: u64,
+    max_khz: u64,
+    governor: String,
+}
+
+impl CpuFreq {
+    fn read(cpufreq_path: &Path) -> Option<CpuFreq> {
+        let read_khz = |name: &str| read_value(&cpufreq_path.join(name)).and_then(|v| v.parse::<u64>().ok());
+
+        Some(CpuFreq {
+            cur_khz: read_khz("scaling_cur_freq")?,
+            min_khz: read_khz("scaling_min_freq").unwrap_or(0),
+            max_khz: read_khz("scaling_max_freq").unwrap_or(0),
+            governor: read_value(&cpufreq_path.join("scaling_governor")).unwrap_or_default(),
+        })
+    }
+}
+
+impl Display for CpuFreq {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "freq: {} MHz (min: {} MHz, max: {} MHz, governor: {})",
+               self.cur_khz / 1000, self.min_khz / 1000, self.max_khz / 1000, self.governor)
+    }
+}
+
+/// Raw counters of a cpuidle state, `time` is in microseconds.
+#[derive(Clone, Debug, Default)]
+struct IdleStateStats {
+    name: String,
+    usage: u64,
+    time: u64,
+}
+
+impl IdleStateStats {
+    fn read_all(cpuidle_path: &Path) -> Vec<IdleStateStats> {
+        let mut states: Vec<(u32, IdleStateStats)> = Vec::new();
+        let entries = match fs::read_dir(cpuidle_path) {
+            Ok(entries) => entries,
+            Err(_) => return Vec::new(),
+        };
+
+        for entry in entries.flatten() {
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            let index = match file_name.strip_prefix("state").and_then(|i| i.parse::<u32>().ok()) {
+                Some(index) => index,
+                None => continue,
+            };
+            let path = entry.path();
+            let read_u64 = |name: &str| read_value(&path.join(name)).and_then(|v| v.parse::<u64>().ok()).unwrap_or(0);
+            states.push((index, IdleStateStats {
+                name: read_value(&path.join("name")).unwrap_or(file_name),
+                usage: read_u64("usage"),
+                time: read_u64("time"),
+            }));
+        }
+
+        states.sort_by_key(|(index, _)| *index);
+        states.into_iter().map(|(_, state)| state).collect()
+    }
+}
+
+/// Time spent in a C-state between two samples, in percent of the wall time.
+#[derive(Clone, Debug, Default)]
+pub struct IdleResidency {
+    name: String,
+    entries_per_sec: f64,
+    residency: f32,
+}
+
+/// Frequency and C-state residency of a single core.
+#[derive(Clone, Debug, Default)]
+pub struct CoreInfo {
+    name: String,
+    freq: Option<CpuFreq>,
+    idle_states: Vec<IdleResidency>,
+}
+
+impl Display for CoreInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, " {}:", self.name)?;
+        if let Some(freq) = &self.freq {
+            write!(f, " {}", freq)?;
+        }
+        if !self.idle_states.is_empty() {
+            write!(f, " idle:")?;
+            for state in &self.idle_states {
+                write!(f, " {}: {:.2}% ({:.1} entries/s)", state.name, state.residency, state.entries_per_sec)?;
+            }
+        }
+        Ok(())
+    }
+}
+
 pub struct CpuInfo {
-    cpus: Vec<(String, f32)>,
+    total: CpuUsage,
+    cpus: Vec<CpuUsage>,
+    cores: Vec<CoreInfo>,
 }
 
 impl Display for CpuInfo {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        for (name, load) in &self.cpus {
-            writeln!(f, " {}: Load: {:.2}%", name, load)?;
+        writeln!(f, "{}", self.total)?;
+        for cpu in &self.cpus {
+            writeln!(f, "{}", cpu)?;
+        }
+        for core in &self.cores {
+            writeln!(f, "{}", core)?;
         }
         Ok(())
     }
 }
 
+impl SampleSource for CpuInfo {
+    fn points(&self) -> Vec<Point> {
+        let mut points = Vec::new();
+        for usage in std::iter::once(&self.total).chain(&self.cpus) {
+            let modes = [
+                ("user", usage.user), ("nice", usage.nice), ("system", usage.system), ("idle", usage.idle),
+                ("iowait", usage.iowait), ("irq", usage.irq), ("softirq", usage.softirq), ("steal", usage.steal),
+                ("guest", usage.guest), ("guest_nice", usage.guest_nice),
+            ];
+            points.push(Point::gauge("cpu_load", Unit::Percent, usage.get_load() as f64).label("cpu", &usage.name));
+            for (mode, value) in modes {
+                points.push(Point::gauge("cpu_time", Unit::Percent, value as f64).label("cpu", &usage.name).label("mode", mode));
+            }
+        }
+        for core in &self.cores {
+            if let Some(freq) = &core.freq {
+                let freqs = [("cpu_freq", freq.cur_khz), ("cpu_freq_min", freq.min_khz), ("cpu_freq_max", freq.max_khz)];
+                for (name, khz) in freqs {
+                    points.push(Point::gauge(name, Unit::Hertz, khz as f64 * 1000.0).label("cpu", &core.name).label("governor", &freq.governor));
+                }
+            }
+            for state in &core.idle_states {
+                points.push(Point::gauge("cpu_idle_residency", Unit::Percent, state.residency as f64).label("cpu", &core.name).label("state", &state.name));
+                points.push(Point::rate("cpu_idle_entries", Unit::Count, state.entries_per_sec).label("cpu", &core.name).label("state", &state.name));
+            }
+        }
+        points
+    }
+}
+
+fn read_value(path: &Path) -> Option<String> {
+    fs::read_to_string(path).ok().map(|value| value.trim().to_string())
+}
+
+pub static COLLECTOR: CollectorSpec = CollectorSpec {
+    name: "cpu",
+    section: "cpu_config",
+    title: "CPU Info",
+    refresh_rate: Duration::from_secs(1),
+    default_options: toml::Table::new,
+    new: |_, paths| Ok(Box::new(CpuInfoCollector::new(paths))),
+};
+
 pub struct CpuInfoCollector {
-    sys: System,
+    prev_stats: HashMap<String, CpuStats>,
+    prev_idle_stats: HashMap<String, Vec<IdleStateStats>>,
+    prev_time: Option<f64>,
+    paths: HostPaths,
 }
 
 impl MetricCollector for CpuInfoCollector {
     fn collect_info(&mut self) -> QueueItem {
-        QueueItem::CPU(self.get_cpu_info())
+        match self.get_cpu_info() {
+            Ok(cpu_info) => QueueItem::sample(&COLLECTOR, &cpu_info),
+            Err(e) => QueueItem::Err(ErrInfo::new(format!("cpu: failed to read {}: {}", PROC_STAT_PATH, e))),
+        }
+    }
+
+    fn reset_counters(&mut self) {
+        self.prev_stats.clear();
+        self.prev_idle_stats.clear();
+        self.prev_time = None;
+    }
+}
+
+impl Default for CpuInfoCollector {
+    fn default() -> Self {
+        Self::new(&HostPaths::default())
     }
 }
 
 impl CpuInfoCollector {
-    pub fn new() -> Self {
+    pub fn new(paths: &HostPaths) -> Self {
         Self {
-            sys: System::new_all(),
+            prev_stats: HashMap::new(),
+            prev_idle_stats: HashMap::new(),
+            prev_time: None,
+            paths: paths.clone(),
         }
     }
 
-    pub fn get_cpu_info(&mut self) -> CpuInfo {
-        self.sys.refresh_all();
+    /// Reads /proc/stat and computes usage since the previous call.
+    /// The first call reports the averages since boot.
+    pub fn get_cpu_info(&mut self) -> Result<CpuInfo, std::io::Error> {
+        let stat = fs::read_to_string(self.paths.resolve(PROC_STAT_PATH))?;
+        let now = self.paths.read_uptime();
+        let elapsed_us = (elapsed_secs(self.prev_time, now) * 1_000_000.0) as u64;
 
-        let cpus = self.sys.cpus();
-        let cpu_data: Vec<(String, f32)> = cpus
+        let mut total = CpuUsage::default();
+        let mut cpus = Vec::new();
+
+        for (name, stats) in stat.lines().filter_map(CpuStats::parse) {
+            let prev = self.prev_stats.get(&name).copied().unwrap_or_default();
+            let usage = CpuUsage::new(&name, &prev, &stats);
+            if name == "cpu" {
+                total = usage;
+            } else {
+                cpus.push(usage);
+            }
+            self.prev_stats.insert(name, stats);
+        }
+
+        let cores = cpus.iter().map(|cpu| self.get_core_info(&cpu.name, elapsed_us)).collect();
+        self.prev_time = now;
+
+        Ok(CpuInfo { total, cpus, cores })
+    }
+
+    /// Cores without cpufreq or cpuidle support (e.g. most virtual machines) report
+    /// neither. Residency and entry rates are reported as zero on the first call.
+    fn get_core_info(&mut self, name: &str, elapsed_us: u64) -> CoreInfo {
+        let cpu_path = self.paths.resolve(CPU_SYSFS_PATH).join(name);
+        let freq = CpuFreq::read(&cpu_path.join("cpufreq"));
+
+        let idle_stats = IdleStateStats::read_all(&cpu_path.join("cpuidle"));
+        let prev_idle_stats = self.prev_idle_stats.get(name);
+        let idle_states = idle_stats
             .iter()
-            .map(|cpu| (cpu.name().to_string(), cpu.cpu_usage()))
+            .map(|state| {
+                let prev = prev_idle_stats.and_then(|prev| prev.iter().find(|p| p.name == state.name));
+                let (prev_usage, prev_time) = prev.map(|p| (p.usage, p.time)).unwrap_or((state.usage, state.time));
+                let (residency, entries_per_sec) = if elapsed_us > 0 {
+                    let residency = (state.time.saturating_sub(prev_time) as f64 * 100.0 / elapsed_us as f64).min(100.0) as f32;
+                    (residency, state.usage.saturating_sub(prev_usage) as f64 * 1_000_000.0 / elapsed_us as f64)
+                } else {
+                    (0.0, 0.0)
+                };
+                IdleResidency { name: state.name.clone(), entries_per_sec, residency }
+            })
             .collect();
+        self.prev_idle_stats.insert(name.to_string(), idle_stats);
 
-        CpuInfo { cpus: cpu_data }
+        CoreInfo { name: name.to_string(), freq, idle_states }
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metric::host::{assert_close, fixture_paths};
+    use crate::sample::{find_point, ValueKind};
+
+    #[test]
+    fn parse_proc_stat_line() {
+        let (name, stats) = CpuStats::parse("cpu0 31292 0 4703 180806 396 0 3 485 0 0").unwrap();
+        assert_eq!(name, "cpu0");
+        assert_eq!(stats.user, 31292);
+        assert_eq!(stats.system, 4703);
+        assert_eq!(stats.idle, 180806);
+        assert_eq!(stats.steal, 485);
+        assert_eq!(stats.total, 217685);
+        assert!(CpuStats::parse("intr 202232 0 0").is_none());
+    }
+
+    #[test]
+    fn usage_between_snapshots() {
+        let mut collector = CpuInfoCollector::new(&fixture_paths("kvm-guest/t0"));
+        collector.get_cpu_info().unwrap();
+        collector.paths = fixture_paths("kvm-guest/t1");
+        let cpu_info = collector.get_cpu_info().unwrap();
+
+        // 172 jiffies: user 54, system 16, idle 98, iowait 3, softirq 1
+        let total = &cpu_info.total;
+        assert_close(total.user as f64, 54.0 * 100.0 / 172.0);
+        assert_close(total.system as f64, 16.0 * 100.0 / 172.0);
+        assert_close(total.idle as f64, 98.0 * 100.0 / 172.0);
+        assert_close(total.iowait as f64, 3.0 * 100.0 / 172.0);
+        assert_close(total.softirq as f64, 1.0 * 100.0 / 172.0);
+        assert_close(total.get_load() as f64, 71.0 * 100.0 / 172.0);
+
+        assert_eq!(cpu_info.cpus.len(), 1);
+        assert_eq!(cpu_info.cpus[0].name, "cpu0");
+        assert_close(cpu_info.cpus[0].user as f64, total.user as f64);
+
+        // the guest exposes neither cpufreq nor cpuidle
+        assert_eq!(cpu_info.cores.len(), 1);
+        assert!(cpu_info.cores[0].freq.is_none());
+        assert!(cpu_info.cores[0].idle_states.is_empty());
+    }
+
+    #[test]
+    fn freq_and_idle_between_snapshots() {
+        let mut collector = CpuInfoCollector::new(&fixture_paths("scenarios/bare-metal/t0"));
+        let cpu_info = collector.get_cpu_info().unwrap();
+        let core = &cpu_info.cores[0];
+        assert_eq!(core.freq.as_ref().unwrap().cur_khz, 2394000);
+        assert!(core.idle_states.iter().all(|state| state.residency == 0.0 && state.entries_per_sec == 0.0));
+
+        collector.paths = fixture_paths("scenarios/bare-metal/t1");
+        let cpu_info = collector.get_cpu_info().unwrap();
+        let core = &cpu_info.cores[0];
+        let freq = core.freq.as_ref().unwrap();
+        assert_eq!((freq.cur_khz, freq.min_khz, freq.max_khz), (2893512, 800000, 3400000));
+        assert_eq!(freq.governor, "schedutil");
+
+        // 2.5 s between the snapshots, residency times are in microseconds
+        let states: Vec<&str> = core.idle_states.iter().map(|state| state.name.as_str()).collect();
+        assert_eq!(states, ["POLL", "C1"]);
+        assert_close(core.idle_states[0].entries_per_sec, 129.0 / 2.5);
+        assert_close(core.idle_states[1].entries_per_sec, 410.0 / 2.5);
+        assert_close(core.idle_states[0].residency as f64, 1.0);
+        assert_close(core.idle_states[1].residency as f64, 50.0);
+
+        let points = cpu_info.points();
+        let freq = find_point(&points, "cpu_freq", &[("cpu", "cpu0"), ("governor", "schedutil")]).unwrap();
+        assert_eq!(freq.get_unit(), Unit::Hertz);
+        assert_eq!(freq.get_value(), 2893512000.0);
+        let residency = find_point(&points, "cpu_idle_residency", &[("cpu", "cpu0"), ("state", "C1")]).unwrap();
+        assert_close(residency.get_value(), 50.0);
+        let entries = find_point(&points, "cpu_idle_entries", &[("cpu", "cpu0"), ("state", "C1")]).unwrap();
+        assert_eq!(entries.get_kind(), ValueKind::Rate);
+        assert_close(entries.get_value(), 410.0 / 2.5);
+    }
+
+    #[test]
+    fn usage_points() {
+        let mut collector = CpuInfoCollector::new(&fixture_paths("kvm-guest/t0"));
+        collector.get_cpu_info().unwrap();
+        collector.paths = fixture_paths("kvm-guest/t1");
+        let points = collector.get_cpu_info().unwrap().points();
+
+        let load = find_point(&points, "cpu_load", &[("cpu", "cpu")]).unwrap();
+        assert_eq!(load.get_kind(), ValueKind::Gauge);
+        assert_eq!(load.get_unit(), Unit::Percent);
+        assert_close(load.get_value(), 71.0 * 100.0 / 172.0);
+        let user = find_point(&points, "cpu_time", &[("cpu", "cpu0"), ("mode", "user")]).unwrap();
+        assert_close(user.get_value(), 54.0 * 100.0 / 172.0);
+        assert!(find_point(&points, "cpu_freq", &[]).is_none());
+    }
+}
@@ -0,0 +1,101 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+
+const UPTIME_PATH: &str = "/proc/uptime";
+
+/// Where collectors find /proc and /sys, e.g. /host/proc and /host/sys when the
+/// host's filesystems are mounted into a container, or a fixture tree in tests.
+#[derive(Clone, Debug)]
+pub struct HostPaths {
+    proc_root: PathBuf,
+    sys_root: PathBuf,
+}
+
+impl Default for HostPaths {
+    fn default() -> Self {
+        Self::new("/proc", "/sys")
+    }
+}
+
+impl HostPaths {
+    pub fn new(proc_root: impl AsRef<Path>, sys_root: impl AsRef<Path>) -> Self {
+        Self {
+            proc_root: proc_root.as_ref().to_path_buf(),
+            sys_root: sys_root.as_ref().to_path_buf(),
+        }
+    }
+
+    /// Maps an absolute /proc or /sys path onto the configured roots, other
+    /// paths are returned as they are.
+    pub fn resolve(&self, path: impl AsRef<Path>) -> PathBuf {
+        let path = path.as_ref();
+        let (root, rest) = if let Ok(rest) = path.strip_prefix("/proc") {
+            (&self.proc_root, rest)
+        } else if let Ok(rest) = path.strip_prefix("/sys") {
+            (&self.sys_root, rest)
+        } else {
+            return path.to_path_buf();
+        };
+        // joining an empty path would add a trailing slash
+        if rest.as_os_str().is_empty() { root.clone() } else { root.join(rest) }
+    }
+
+    /// Seconds since boot, used as the time base of rates so they only depend
+    /// on what was read from the roots.
+    pub fn read_uptime(&self) -> Option<f64> {
+        fs::read_to_string(self.resolve(UPTIME_PATH)).ok()?.split_whitespace().next()?.parse::<f64>().ok()
+    }
+}
+
+/// Seconds between two uptime readings, 0 on the first sample or when the uptime
+/// is not available.
+pub fn elapsed_secs(prev: Option<f64>, now: Option<f64>) -> f64 {
+    match (prev, now) {
+        (Some(prev), Some(now)) if now > prev => now - prev,
+        _ => 0.0,
+    }
+}
+
+/// Paths of a fixture snapshot under tests/fixtures, e.g. "kvm-guest/t0".
+#[cfg(test)]
+pub(crate) fn fixture_paths(snapshot: &str) -> HostPaths {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(snapshot);
+    HostPaths::new(root.join("proc"), root.join("sys"))
+}
+
+#[cfg(test)]
+pub(crate) fn assert_close(actual: f64, expected: f64) {
+    assert!((actual - expected).abs() < 1e-3, "{} != {}", actual, expected);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_maps_proc_and_sys() {
+        let paths = HostPaths::new("/host/proc", "/host/sys");
+        assert_eq!(paths.resolve("/proc/stat"), Path::new("/host/proc/stat"));
+        assert_eq!(paths.resolve("/proc").to_str(), Some("/host/proc"));
+        assert_eq!(paths.resolve("/sys/class/net"), Path::new("/host/sys/class/net"));
+        assert_eq!(paths.resolve("/sys/fs/cgroup"), Path::new("/host/sys/fs/cgroup"));
+        assert_eq!(paths.resolve("/dev/kmsg"), Path::new("/dev/kmsg"));
+        assert_eq!(paths.resolve("/processes"), Path::new("/processes"));
+    }
+
+    #[test]
+    fn read_uptime_from_fixture() {
+        assert_eq!(fixture_paths("kvm-guest/t0").read_uptime(), Some(2174.67));
+        assert_eq!(fixture_paths("kvm-guest/t1").read_uptime(), Some(2176.38));
+        assert_eq!(fixture_paths("missing").read_uptime(), None);
+    }
+
+    #[test]
+    fn elapsed_secs_needs_two_readings() {
+        assert_eq!(elapsed_secs(None, Some(10.0)), 0.0);
+        assert_eq!(elapsed_secs(Some(10.0), None), 0.0);
+        assert_eq!(elapsed_secs(Some(10.0), Some(9.0)), 0.0);
+        assert_eq!(elapsed_secs(Some(10.0), Some(12.5)), 2.5);
+    }
+}
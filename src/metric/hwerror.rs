@@ -0,0 +1,300 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::queue::{EventInfo, QueueItem};
+use crate::metric::{CollectorSpec, MetricCollector};
+use crate::metric::host::HostPaths;
+use crate::sample::{Point, SampleSource, Unit};
+
+
+const EDAC_MC_PATH: &str = "/sys/devices/system/edac/mc";
+const MACHINECHECK_PATH: &str = "/sys/devices/system/machinecheck";
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum HwErrorKind {
+    Corrected,
+    Uncorrected,
+    /// Errors counted by an AMD MCA threshold block.
+    MachineCheck,
+}
+
+fn kind_to_str(kind: HwErrorKind) -> &'static str {
+    match kind {
+        HwErrorKind::Corrected => "corrected",
+        HwErrorKind::Uncorrected => "uncorrected",
+        HwErrorKind::MachineCheck => "machine check",
+    }
+}
+
+pub struct HwErrorInfo {
+    counters: Vec<HwErrorCounter>,
+}
+
+impl Display for HwErrorInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.counters.is_empty() {
+            return writeln!(f, "No EDAC memory controllers or machine check counters");
+        }
+        for counter in &self.counters {
+            writeln!(f, "{}", counter)?;
+        }
+        Ok(())
+    }
+}
+
+impl SampleSource for HwErrorInfo {
+    fn points(&self) -> Vec<Point> {
+        self.counters
+            .iter()
+            .map(|counter| {
+                Point::counter("hw_errors", Unit::Count, counter.count as f64)
+                    .label("source", &counter.source)
+                    .label("label", &counter.label)
+                    .label("kind", kind_to_str(counter.kind))
+            })
+            .collect()
+    }
+}
+
+/// A single error counter, `source` is its path relative to the edac or
+/// machinecheck directory, e.g. "mc0/dimm1".
+pub struct HwErrorCounter {
+    source: String,
+    label: String,
+    kind: HwErrorKind,
+    count: u64,
+}
+
+impl HwErrorCounter {
+    fn key(&self) -> String {
+        format!("{} {}", self.source, kind_to_str(self.kind))
+    }
+}
+
+impl Display for HwErrorCounter {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, " {}", self.source)?;
+        if !self.label.is_empty() {
+            write!(f, " ({})", self.label)?;
+        }
+        write!(f, ": {} errors: {}", kind_to_str(self.kind), self.count)
+    }
+}
+
+fn read_string(path: &Path) -> Option<String> {
+    fs::read_to_string(path).ok().map(|s| s.trim().to_string())
+}
+
+fn read_u64(path: &Path) -> Option<u64> {
+    read_string(path)?.parse::<u64>().ok()
+}
+
+/// Entries of `path` named `prefix` followed by a number, sorted by name.
+fn numbered_entries(path: &Path, prefix: &str) -> Vec<PathBuf> {
+    let mut entries: Vec<PathBuf> = match fs::read_dir(path) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| {
+                let name = entry.file_name().to_string_lossy().to_string();
+                name.strip_prefix(prefix).is_some_and(|id| id.parse::<u32>().is_ok())
+            })
+            .map(|entry| entry.path())
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    entries.sort();
+    entries
+}
+
+fn entry_name(path: &Path) -> String {
+    path.file_name().unwrap_or_default().to_string_lossy().to_string()
+}
+
+/// Reads the `<prefix>ce_count` and `<prefix>ue_count` files of an edac directory.
+fn read_edac_counters(path: &Path, source: &str, label: &str, prefix: &str, counters: &mut Vec<HwErrorCounter>) {
+    let files = [(HwErrorKind::Corrected, "ce_count"), (HwErrorKind::Uncorrected, "ue_count")];
+    for (kind, file) in files {
+        if let Some(count) = read_u64(&path.join(format!("{}{}", prefix, file))) {
+            counters.push(HwErrorCounter { source: source.to_string(), label: label.to_string(), kind, count });
+        }
+    }
+}
+
+pub static COLLECTOR: CollectorSpec = CollectorSpec {
+    name: "hwerror",
+    section: "hwerror_config",
+    title: "Hardware Errors Info",
+    refresh_rate: Duration::from_secs(60),
+    default_options: toml::Table::new,
+    new: |_, paths| Ok(Box::new(HwErrorInfoCollector::new(paths))),
+};
+
+pub struct HwErrorInfoCollector {
+    paths: HostPaths,
+    prev_counts: Option<HashMap<String, u64>>,
+    events: Vec<QueueItem>,
+}
+
+impl MetricCollector for HwErrorInfoCollector {
+    fn collect_info(&mut self) -> QueueItem {
+        QueueItem::sample(&COLLECTOR, &self.get_hwerror_info())
+    }
+
+    fn collect_events(&mut self) -> Vec<QueueItem> {
+        std::mem::take(&mut self.events)
+    }
+}
+
+impl HwErrorInfoCollector {
+    pub fn new(paths: &HostPaths) -> Self {
+        Self {
+            paths: paths.clone(),
+            prev_counts: None,
+            events: Vec::new(),
+        }
+    }
+
+    /// Any counter increase since the previous call is sent as an event, counts
+    /// found on the first call are only reported.
+    pub fn get_hwerror_info(&mut self) -> HwErrorInfo {
+        let mut counters = Vec::new();
+        self.read_edac(&mut counters);
+        self.read_machinecheck(&mut counters);
+
+        let counts: HashMap<String, u64> = counters.iter().map(|counter| (counter.key(), counter.count)).collect();
+        if let Some(prev_counts) = &self.prev_counts {
+            for counter in &counters {
+                let prev = prev_counts.get(&counter.key()).copied().unwrap_or(0);
+                if counter.count > prev {
+                    let message = format!("{} errors increased by {}:{}", kind_to_str(counter.kind), counter.count - prev, counter);
+                    self.events.push(QueueItem::Event(EventInfo::new(COLLECTOR.name, message)));
+                }
+            }
+        }
+        self.prev_counts = Some(counts);
+
+        HwErrorInfo { counters }
+    }
+
+    /// Memory controllers with their csrow/channel counters (legacy layout) and
+    /// dimm/rank counters (layout since 3.6).
+    fn read_edac(&self, counters: &mut Vec<HwErrorCounter>) {
+        for mc_path in numbered_entries(&self.paths.resolve(EDAC_MC_PATH), "mc") {
+            let mc = entry_name(&mc_path);
+            let mc_name = read_string(&mc_path.join("mc_name")).unwrap_or_default();
+            read_edac_counters(&mc_path, &mc, &mc_name, "", counters);
+
+            for csrow_path in numbered_entries(&mc_path, "csrow") {
+                let csrow = format!("{}/{}", mc, entry_name(&csrow_path));
+                read_edac_counters(&csrow_path, &csrow, "", "", counters);
+
+                for channel in 0.. {
+                    let count = match read_u64(&csrow_path.join(format!("ch{}_ce_count", channel))) {
+                        Some(count) => count,
+                        None => break,
+                    };
+                    let label = read_string(&csrow_path.join(format!("ch{}_dimm_label", channel))).unwrap_or_default();
+                    counters.push(HwErrorCounter {
+                        source: format!("{}/ch{}", csrow, channel),
+                        label,
+                        kind: HwErrorKind::Corrected,
+                        count,
+                    });
+                }
+            }
+
+            for prefix in ["dimm", "rank"] {
+                for dimm_path in numbered_entries(&mc_path, prefix) {
+                    let dimm = format!("{}/{}", mc, entry_name(&dimm_path));
+                    let label = read_string(&dimm_path.join("dimm_label")).unwrap_or_default();
+                    read_edac_counters(&dimm_path, &dimm, &label, "dimm_", counters);
+                }
+            }
+        }
+    }
+
+    /// The machinecheck devices only expose counters for AMD MCA threshold
+    /// blocks, as machinecheck<cpu>/<bank>/<block>/error_count.
+    fn read_machinecheck(&self, counters: &mut Vec<HwErrorCounter>) {
+        for cpu_path in numbered_entries(&self.paths.resolve(MACHINECHECK_PATH), "machinecheck") {
+            let mut bank_paths: Vec<PathBuf> = fs::read_dir(&cpu_path)
+                .map(|entries| entries.flatten().map(|entry| entry.path()).filter(|path| path.is_dir()).collect())
+                .unwrap_or_default();
+            bank_paths.sort();
+
+            for bank_path in bank_paths {
+                let mut block_paths: Vec<PathBuf> = fs::read_dir(&bank_path)
+                    .map(|entries| entries.flatten().map(|entry| entry.path()).collect())
+                    .unwrap_or_default();
+                block_paths.sort();
+
+                for block_path in block_paths {
+                    if let Some(count) = read_u64(&block_path.join("error_count")) {
+                        counters.push(HwErrorCounter {
+                            source: format!("{}/{}/{}", entry_name(&cpu_path), entry_name(&bank_path), entry_name(&block_path)),
+                            label: String::new(),
+                            kind: HwErrorKind::MachineCheck,
+                            count,
+                        });
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metric::host::fixture_paths;
+    use crate::queue::event_messages;
+    use crate::sample::find_point;
+
+    fn counts(hwerror_info: &HwErrorInfo) -> Vec<(String, &str, u64)> {
+        hwerror_info.counters.iter().map(|counter| (counter.source.clone(), kind_to_str(counter.kind), counter.count)).collect()
+    }
+
+    #[test]
+    fn hwerror_between_snapshots() {
+        let mut collector = HwErrorInfoCollector::new(&fixture_paths("scenarios/bare-metal/t0"));
+        let hwerror_info = collector.get_hwerror_info();
+        let expected = [
+            ("mc0", "corrected", 3),
+            ("mc0", "uncorrected", 0),
+            ("mc0/csrow0", "corrected", 3),
+            ("mc0/csrow0", "uncorrected", 0),
+            ("mc0/csrow0/ch0", "corrected", 3),
+            ("mc0/dimm0", "corrected", 3),
+            ("mc0/dimm0", "uncorrected", 0),
+            ("machinecheck0/northbridge/misc0", "machine check", 0),
+        ];
+        let expected: Vec<(String, &str, u64)> = expected.iter().map(|(source, kind, count)| (source.to_string(), *kind, *count)).collect();
+        assert_eq!(counts(&hwerror_info), expected);
+        assert_eq!(hwerror_info.counters[0].label, "Skylake Socket#0 IMC#0");
+        assert_eq!(hwerror_info.counters[4].label, "CPU_SrcID#0_Ha#0_Chan#0_DIMM#0");
+        assert_eq!(hwerror_info.counters[5].label, "CPU_SrcID#0_MC#0_Chan#0_DIMM#0");
+        // counts found on the first run are not events
+        assert!(collector.collect_events().is_empty());
+
+        collector.paths = fixture_paths("scenarios/bare-metal/t1");
+        let hwerror_info = collector.get_hwerror_info();
+        let points = hwerror_info.points();
+        let dimm = find_point(&points, "hw_errors", &[("source", "mc0/dimm0"), ("kind", "corrected")]).unwrap();
+        assert_eq!(dimm.get_value(), 5.0);
+        let machine_check = find_point(&points, "hw_errors", &[("source", "machinecheck0/northbridge/misc0")]).unwrap();
+        assert_eq!(machine_check.get_value(), 1.0);
+
+        let events = collector.collect_events();
+        assert_eq!(event_messages(&events), [
+            "corrected errors increased by 2: mc0 (Skylake Socket#0 IMC#0): corrected errors: 5",
+            "corrected errors increased by 2: mc0/csrow0: corrected errors: 5",
+            "corrected errors increased by 2: mc0/csrow0/ch0 (CPU_SrcID#0_Ha#0_Chan#0_DIMM#0): corrected errors: 5",
+            "corrected errors increased by 2: mc0/dimm0 (CPU_SrcID#0_MC#0_Chan#0_DIMM#0): corrected errors: 5",
+            "machine check errors increased by 1: machinecheck0/northbridge/misc0: machine check errors: 1",
+        ]);
+        assert!(collector.collect_events().is_empty());
+    }
+}
@@ -0,0 +1,367 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::{from_options, to_options};
+use crate::queue::{ErrInfo, QueueItem};
+use crate::metric::{CollectorSpec, MetricCollector};
+use crate::metric::host::{elapsed_secs, HostPaths};
+use crate::sample::{Point, SampleSource, Unit};
+
+
+const INTERRUPTS_PATH: &str = "/proc/interrupts";
+const SOFTIRQS_PATH: &str = "/proc/softirqs";
+
+pub struct InterruptsInfo {
+    cpus: Vec<String>,
+    cpu_rates: Vec<f64>,
+    irqs_count: usize,
+    irqs_info: Vec<IrqRate>,
+    softirqs_info: Vec<IrqRate>,
+}
+
+/// Rate of one /proc/interrupts or /proc/softirqs line.
+pub struct IrqRate {
+    name: String,
+    description: String,
+    rate: f64,
+    cpu_rates: Vec<f64>,
+    imbalance: f64,
+    imbalanced: bool,
+}
+
+impl Display for InterruptsInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Interrupts per cpu:")?;
+        for (cpu, rate) in self.cpus.iter().zip(&self.cpu_rates) {
+            write!(f, " {}: {:.2}/s", cpu, rate)?;
+        }
+        writeln!(f)?;
+        writeln!(f, "Top {} of {} irqs:", self.irqs_info.len(), self.irqs_count)?;
+        for irq_info in &self.irqs_info {
+            writeln!(f, "{}", irq_info)?;
+        }
+        writeln!(f, "Softirqs:")?;
+        for softirq_info in &self.softirqs_info {
+            writeln!(f, "{}", softirq_info)?;
+        }
+        Ok(())
+    }
+}
+
+impl SampleSource for InterruptsInfo {
+    fn points(&self) -> Vec<Point> {
+        let mut points: Vec<Point> = self.cpus.iter().zip(&self.cpu_rates)
+            .map(|(cpu, rate)| Point::rate("cpu_interrupts", Unit::Count, *rate).label("cpu", cpu))
+            .collect();
+        points.push(Point::gauge("irqs", Unit::Count, self.irqs_count as f64));
+        for (name, irqs_info) in [("irq", &self.irqs_info), ("softirq", &self.softirqs_info)] {
+            for irq_info in irqs_info {
+                points.push(Point::rate(name, Unit::Count, irq_info.rate).label(name, &irq_info.name).label("description", &irq_info.description));
+                points.push(Point::gauge(format!("{}_imbalance", name), Unit::Ratio, irq_info.imbalance).label(name, &irq_info.name));
+                for (cpu, rate) in self.cpus.iter().zip(&irq_info.cpu_rates) {
+                    points.push(Point::rate(format!("{}_cpu", name), Unit::Count, *rate).label(name, &irq_info.name).label("cpu", cpu));
+                }
+            }
+        }
+        points
+    }
+}
+
+impl Display for IrqRate {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, " {}", self.name)?;
+        if !self.description.is_empty() {
+            write!(f, " ({})", self.description)?;
+        }
+        write!(f, ": {:.2}/s, imbalance: {:.2}", self.rate, self.imbalance)?;
+        if self.imbalanced {
+            write!(f, " [IMBALANCED]")?;
+        }
+        if !self.cpu_rates.is_empty() {
+            write!(f, ", per cpu:")?;
+            for rate in &self.cpu_rates {
+                write!(f, " {:.2}", rate)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Lines of /proc/interrupts counting for the whole system rather than per cpu.
+const SYSTEM_WIDE_IRQS: [&str; 2] = ["ERR", "MIS"];
+
+/// Per-cpu counters of one interrupt line, or a single system-wide counter.
+#[derive(Clone, Debug, Default)]
+struct IrqCounters {
+    description: String,
+    counts: Vec<u64>,
+    per_cpu: bool,
+}
+
+/// Parses /proc/interrupts or /proc/softirqs. The header holds the online cpus,
+/// lines like ERR or MIS carry a single value.
+fn parse_irq_table(content: &str) -> (Vec<String>, Vec<(String, IrqCounters)>) {
+    let mut lines = content.lines();
+    let cpus: Vec<String> = lines.next().unwrap_or_default().split_whitespace().map(|cpu| cpu.to_string()).collect();
+
+    let mut irqs = Vec::new();
+    for line in lines {
+        let (name, rest) = match line.split_once(':') {
+            Some(line) => line,
+            None => continue,
+        };
+
+        let mut counts = Vec::with_capacity(cpus.len());
+        let mut fields = rest.split_whitespace().peekable();
+        while counts.len() < cpus.len() {
+            match fields.peek().and_then(|field| field.parse::<u64>().ok()) {
+                Some(count) => {
+                    counts.push(count);
+                    fields.next();
+                },
+                None => break,
+            }
+        }
+        let description = fields.collect::<Vec<&str>>().join(" ");
+        let name = name.trim();
+        let per_cpu = !SYSTEM_WIDE_IRQS.contains(&name);
+
+        irqs.push((name.to_string(), IrqCounters { description, counts, per_cpu }));
+    }
+
+    (cpus, irqs)
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(default)]
+pub struct InterruptsConfig {
+    top_n: usize,
+    imbalance_threshold: f64,
+    imbalance_min_rate: f64,
+}
+
+impl Default for InterruptsConfig {
+    fn default() -> Self {
+        InterruptsConfig {
+            top_n: 10,
+            imbalance_threshold: 0.8,
+            imbalance_min_rate: 100.0,
+        }
+    }
+}
+
+impl InterruptsConfig {
+    pub fn get_top_n(&self) -> usize {
+        self.top_n
+    }
+
+    /// Imbalance score (0..1) from which an irq line is flagged.
+    pub fn get_imbalance_threshold(&self) -> f64 {
+        self.imbalance_threshold
+    }
+
+    /// Lines below this rate per second are never flagged as imbalanced.
+    pub fn get_imbalance_min_rate(&self) -> f64 {
+        self.imbalance_min_rate
+    }
+}
+
+pub static COLLECTOR: CollectorSpec = CollectorSpec {
+    name: "irq",
+    section: "interrupts_config",
+    title: "Interrupts Info",
+    refresh_rate: Duration::from_secs(5),
+    default_options: || to_options(&InterruptsConfig::default()),
+    new: |options, paths| Ok(Box::new(InterruptsInfoCollector::new(&from_options(options)?, paths))),
+};
+
+pub struct InterruptsInfoCollector {
+    top_n: usize,
+    imbalance_threshold: f64,
+    imbalance_min_rate: f64,
+    prev_irqs: HashMap<String, IrqCounters>,
+    prev_softirqs: HashMap<String, IrqCounters>,
+    prev_time: Option<f64>,
+    paths: HostPaths,
+}
+
+impl MetricCollector for InterruptsInfoCollector {
+    fn collect_info(&mut self) -> QueueItem {
+        match self.get_interrupts_info() {
+            Ok(interrupts_info) => QueueItem::sample(&COLLECTOR, &interrupts_info),
+            Err(e) => QueueItem::Err(ErrInfo::new(format!("irq: failed to read interrupt stats: {}", e))),
+        }
+    }
+
+    fn reset_counters(&mut self) {
+        self.prev_irqs.clear();
+        self.prev_softirqs.clear();
+        self.prev_time = None;
+    }
+}
+
+impl InterruptsInfoCollector {
+    pub fn new(config: &InterruptsConfig, paths: &HostPaths) -> Self {
+        Self {
+            top_n: config.get_top_n(),
+            imbalance_threshold: config.get_imbalance_threshold(),
+            imbalance_min_rate: config.get_imbalance_min_rate(),
+            prev_irqs: HashMap::new(),
+            prev_softirqs: HashMap::new(),
+            prev_time: None,
+            paths: paths.clone(),
+        }
+    }
+
+    /// Rates are computed since the previous call and reported as zero on the first one.
+    pub fn get_interrupts_info(&mut self) -> Result<InterruptsInfo, std::io::Error> {
+        let (cpus, irqs) = parse_irq_table(&fs::read_to_string(self.paths.resolve(INTERRUPTS_PATH))?);
+        let (_, softirqs) = parse_irq_table(&fs::read_to_string(self.paths.resolve(SOFTIRQS_PATH))?);
+
+        let now = self.paths.read_uptime();
+        let elapsed_secs = elapsed_secs(self.prev_time, now);
+
+        let mut cpu_rates = vec![0.0; cpus.len()];
+        let mut irqs_info = Vec::new();
+        for (name, counters) in &irqs {
+            let irq_rate = self.get_irq_rate(name, counters, self.prev_irqs.get(name), elapsed_secs);
+            for (cpu_rate, rate) in cpu_rates.iter_mut().zip(&irq_rate.cpu_rates) {
+                *cpu_rate += rate;
+            }
+            irqs_info.push(irq_rate);
+        }
+        let irqs_count = irqs_info.len();
+        irqs_info.sort_by(|a, b| b.rate.total_cmp(&a.rate));
+        irqs_info.truncate(self.top_n);
+
+        let softirqs_info = softirqs
+            .iter()
+            .map(|(name, counters)| self.get_irq_rate(name, counters, self.prev_softirqs.get(name), elapsed_secs))
+            .collect();
+
+        self.prev_irqs = irqs.into_iter().collect();
+        self.prev_softirqs = softirqs.into_iter().collect();
+        self.prev_time = now;
+
+        Ok(InterruptsInfo { cpus, cpu_rates, irqs_count, irqs_info, softirqs_info })
+    }
+
+    /// The imbalance score is 0 when the load is spread evenly over all cpus and
+    /// 1 when a single cpu takes all of it. System-wide lines have no per-cpu rates.
+    fn get_irq_rate(&self, name: &str, curr: &IrqCounters, prev: Option<&IrqCounters>, elapsed_secs: f64) -> IrqRate {
+        let mut cpu_rates: Vec<f64> = curr.counts
+            .iter()
+            .enumerate()
+            .map(|(cpu, count)| {
+                let prev = prev.and_then(|prev| prev.counts.get(cpu)).copied().unwrap_or(*count);
+                if elapsed_secs > 0.0 { count.saturating_sub(prev) as f64 / elapsed_secs } else { 0.0 }
+            })
+            .collect();
+        let rate: f64 = cpu_rates.iter().sum();
+        if !curr.per_cpu {
+            cpu_rates.clear();
+        }
+
+        let cpus = cpu_rates.len() as f64;
+        let max_rate = cpu_rates.iter().cloned().fold(0.0, f64::max);
+        let imbalance = if cpus > 1.0 && rate > 0.0 {
+            ((max_rate / rate - 1.0 / cpus) / (1.0 - 1.0 / cpus)).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+
+        IrqRate {
+            name: name.to_string(),
+            description: curr.description.clone(),
+            rate,
+            cpu_rates,
+            imbalance,
+            imbalanced: rate >= self.imbalance_min_rate && imbalance >= self.imbalance_threshold,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metric::host::{assert_close, fixture_paths};
+    use crate::sample::find_point;
+
+    #[test]
+    fn parse_irq_table_lines() {
+        let (cpus, irqs) = parse_irq_table("           CPU0       CPU1\n 24:   1   2  IO-APIC   5-edge   ACPI:Ged\nERR:   3\n");
+        assert_eq!(cpus, ["CPU0", "CPU1"]);
+        assert_eq!(irqs[0].0, "24");
+        assert_eq!(irqs[0].1.counts, [1, 2]);
+        assert_eq!(irqs[0].1.description, "IO-APIC 5-edge ACPI:Ged");
+        assert!(irqs[0].1.per_cpu);
+        assert_eq!(irqs[1].0, "ERR");
+        assert_eq!(irqs[1].1.counts, [3]);
+        assert!(!irqs[1].1.per_cpu);
+    }
+
+    #[test]
+    fn system_wide_lines_are_not_per_cpu() {
+        let collector = InterruptsInfoCollector::new(&InterruptsConfig::default(), &fixture_paths("kvm-guest/t0"));
+        let prev = IrqCounters { description: String::new(), counts: vec![10], per_cpu: false };
+        let curr = IrqCounters { description: String::new(), counts: vec![14], per_cpu: false };
+        let irq_rate = collector.get_irq_rate("ERR", &curr, Some(&prev), 2.0);
+        assert_close(irq_rate.rate, 2.0);
+        assert!(irq_rate.cpu_rates.is_empty());
+
+        let interrupts_info = InterruptsInfo {
+            cpus: vec![String::from("CPU0"), String::from("CPU1")],
+            cpu_rates: vec![0.0, 0.0],
+            irqs_count: 1,
+            irqs_info: vec![irq_rate],
+            softirqs_info: Vec::new(),
+        };
+        let points = interrupts_info.points();
+        assert_close(find_point(&points, "irq", &[("irq", "ERR")]).unwrap().get_value(), 2.0);
+        assert!(find_point(&points, "irq_cpu", &[("irq", "ERR")]).is_none());
+    }
+
+    #[test]
+    fn irq_rates_between_snapshots() {
+        let mut collector = InterruptsInfoCollector::new(&InterruptsConfig::default(), &fixture_paths("kvm-guest/t0"));
+        collector.get_interrupts_info().unwrap();
+        collector.paths = fixture_paths("kvm-guest/t1");
+        let interrupts_info = collector.get_interrupts_info().unwrap();
+
+        assert_eq!(interrupts_info.cpus, ["CPU0"]);
+        assert_eq!(interrupts_info.irqs_count, 37);
+        assert_close(interrupts_info.cpu_rates[0], 384.0 / 1.71);
+
+        let top = &interrupts_info.irqs_info[0];
+        assert_eq!(top.name, "LOC");
+        assert_eq!(top.description, "Local timer interrupts");
+        assert_close(top.rate, 253.0 / 1.71);
+        // a single cpu can't be imbalanced
+        assert_close(top.imbalance, 0.0);
+        assert_eq!(interrupts_info.irqs_info[1].name, "36");
+        assert_close(interrupts_info.irqs_info[1].rate, 125.0 / 1.71);
+
+        let rcu = interrupts_info.softirqs_info.iter().find(|softirq| softirq.name == "RCU").unwrap();
+        assert_close(rcu.rate, 428.0 / 1.71);
+    }
+
+    #[test]
+    fn imbalance_score() {
+        let collector = InterruptsInfoCollector::new(&InterruptsConfig::default(), &fixture_paths("kvm-guest/t0"));
+        let prev = IrqCounters { description: String::new(), counts: vec![0, 0, 0, 0], per_cpu: true };
+
+        let even = IrqCounters { description: String::new(), counts: vec![100, 100, 100, 100], per_cpu: true };
+        let irq_rate = collector.get_irq_rate("1", &even, Some(&prev), 1.0);
+        assert_close(irq_rate.imbalance, 0.0);
+        assert!(!irq_rate.imbalanced);
+
+        let single = IrqCounters { description: String::new(), counts: vec![400, 0, 0, 0], per_cpu: true };
+        let irq_rate = collector.get_irq_rate("1", &single, Some(&prev), 1.0);
+        assert_close(irq_rate.imbalance, 1.0);
+        assert!(irq_rate.imbalanced);
+    }
+}
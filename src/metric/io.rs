@@ -1,59 +1,508 @@
+use std::collections::HashMap;
+use std::ffi::CString;
 use std::fmt::{Display, Formatter};
+use std::fs;
+use std::mem::MaybeUninit;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::time::Duration;
 
-use sysinfo::{Disks, DiskUsage};
+use serde::{Deserialize, Serialize};
 
-use crate::queue::QueueItem;
-use crate::metric::MetricCollector;
+use crate::config::{from_options, to_options};
+use crate::queue::{ErrInfo, EventInfo, QueueItem};
+use crate::metric::{CollectorSpec, MetricCollector};
+use crate::metric::host::{elapsed_secs, HostPaths};
+use crate::sample::{Point, SampleSource, Unit};
 
 
+const DISKSTATS_PATH: &str = "/proc/diskstats";
+const MOUNTINFO_PATH: &str = "/proc/1/mountinfo";
+/// Root directory of the process the mount table is read for.
+const MOUNT_ROOT_PATH: &str = "/proc/1/root";
+const SYS_CLASS_BLOCK_PATH: &str = "/sys/class/block";
+const SECTOR_SIZE: u64 = 512;
+
 pub struct IOInfo {
-    disks_info: Vec<DiskInfo>
+    disks_info: Vec<DiskInfo>,
+    devices_info: Vec<BlockDeviceInfo>,
 }
 
+/// Mounted filesystem usage from /proc/1/mountinfo and statvfs, space in bytes.
 pub struct DiskInfo {
     name: String,
     mount_point: String,
+    fs_type: String,
+    options: String,
+    read_only: bool,
     total_space: u64,
     available_space: u64,
+    total_inodes: u64,
+    free_inodes: u64,
+}
+
+/// Block device activity between two /proc/diskstats samples.
+pub struct BlockDeviceInfo {
+    name: String,
+    reads_per_sec: f64,
+    writes_per_sec: f64,
+    read_bytes_per_sec: f64,
+    write_bytes_per_sec: f64,
+    read_await: f64,
+    write_await: f64,
+    await_ms: f64,
+    queue_depth: f64,
+    utilization: f64,
 }
 
 impl Display for IOInfo {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         for disk_info in &self.disks_info {
-            write!(f, "name: {}, mount_point: {}, total_space: {} MB, available_space: {} MB\n", disk_info.name, disk_info.mount_point, disk_info.total_space, disk_info.available_space)?;
+            writeln!(f, "{}", disk_info)?;
+        }
+        for device_info in &self.devices_info {
+            writeln!(f, "{}", device_info)?;
         }
         Ok(())
     }
 }
 
-pub struct IOInfoCollector { }
+impl SampleSource for IOInfo {
+    fn points(&self) -> Vec<Point> {
+        let mut points = Vec::new();
+        for disk in &self.disks_info {
+            let values = [
+                Point::gauge("fs_space_total", Unit::Bytes, disk.total_space as f64),
+                Point::gauge("fs_space_available", Unit::Bytes, disk.available_space as f64),
+                Point::gauge("fs_inodes_total", Unit::Count, disk.total_inodes as f64),
+                Point::gauge("fs_inodes_free", Unit::Count, disk.free_inodes as f64),
+                Point::gauge("fs_read_only", Unit::Count, if disk.read_only { 1.0 } else { 0.0 }),
+            ];
+            points.extend(values.into_iter().map(|point| {
+                point.label("device", &disk.name).label("mount_point", &disk.mount_point).label("fs_type", &disk.fs_type)
+            }));
+        }
+        for device in &self.devices_info {
+            let values = [
+                Point::rate("disk_reads", Unit::Count, device.reads_per_sec),
+                Point::rate("disk_writes", Unit::Count, device.writes_per_sec),
+                Point::rate("disk_read_bytes", Unit::Bytes, device.read_bytes_per_sec),
+                Point::rate("disk_write_bytes", Unit::Bytes, device.write_bytes_per_sec),
+                Point::gauge("disk_read_await", Unit::Milliseconds, device.read_await),
+                Point::gauge("disk_write_await", Unit::Milliseconds, device.write_await),
+                Point::gauge("disk_await", Unit::Milliseconds, device.await_ms),
+                Point::gauge("disk_queue_depth", Unit::Count, device.queue_depth),
+                Point::gauge("disk_utilization", Unit::Percent, device.utilization),
+            ];
+            points.extend(values.into_iter().map(|point| point.label("device", &device.name)));
+        }
+        points
+    }
+}
+
+impl Display for DiskInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "name: {}, mount_point: {}, type: {}, options: {}{}, total_space: {} MB, available_space: {} MB, inodes: {}, free_inodes: {}",
+               self.name, self.mount_point, self.fs_type, self.options, if self.read_only { " [READ-ONLY]" } else { "" },
+               self.total_space / (1024 * 1024), self.available_space / (1024 * 1024), self.total_inodes, self.free_inodes)
+    }
+}
+
+impl Display for BlockDeviceInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "device: {}, r/s: {:.2}, w/s: {:.2}, read: {:.2} kB/s, write: {:.2} kB/s, r_await: {:.2} ms, w_await: {:.2} ms, await: {:.2} ms, queue_depth: {:.2}, util: {:.2}%",
+               self.name, self.reads_per_sec, self.writes_per_sec, self.read_bytes_per_sec / 1024.0, self.write_bytes_per_sec / 1024.0,
+               self.read_await, self.write_await, self.await_ms, self.queue_depth, self.utilization)
+    }
+}
+
+/// A single /proc/<pid>/mountinfo line, see proc(5).
+struct MountInfo {
+    source: String,
+    mount_point: String,
+    fs_type: String,
+    options: String,
+    read_only: bool,
+}
+
+impl MountInfo {
+    /// Parses "36 35 98:0 /mnt1 /mnt2 rw,noatime master:1 - ext3 /dev/root rw,errors=continue".
+    fn parse(line: &str) -> Option<Self> {
+        let (mount_fields, fs_fields) = line.split_once(" - ")?;
+        let mount_fields: Vec<&str> = mount_fields.split_whitespace().collect();
+        let fs_fields: Vec<&str> = fs_fields.split_whitespace().collect();
+        let mount_options = mount_fields.get(5)?;
+        let super_options = fs_fields.get(2).copied().unwrap_or_default();
+
+        // ext4 errors=remount-ro only changes the superblock options
+        let read_only = mount_options.split(',').any(|o| o == "ro") || super_options.split(',').any(|o| o == "ro");
+
+        Some(MountInfo {
+            source: unescape_octal(fs_fields.get(1)?),
+            mount_point: unescape_octal(mount_fields.get(4)?),
+            fs_type: fs_fields.first()?.to_string(),
+            options: format!("{},{}", mount_options, super_options),
+            read_only,
+        })
+    }
+}
+
+/// Spaces, tabs, newlines and backslashes are escaped as \NNN octal in mountinfo.
+fn unescape_octal(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            let code: String = chars.clone().take(3).collect();
+            if let Ok(code) = u8::from_str_radix(&code, 8) {
+                result.push(code as char);
+                chars.nth(2);
+                continue;
+            }
+        }
+        result.push(c);
+    }
+    result
+}
+
+fn statvfs(path: &Path) -> Result<libc::statvfs, std::io::Error> {
+    let c_path = CString::new(path.as_os_str().as_bytes())?;
+    let mut stat = MaybeUninit::<libc::statvfs>::uninit();
+    let res = unsafe { libc::statvfs(c_path.as_ptr(), stat.as_mut_ptr()) };
+    if res != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(unsafe { stat.assume_init() })
+}
+
+/// Counters of a single /proc/diskstats line, see Documentation/admin-guide/iostats.rst.
+#[derive(Clone, Copy, Default, Debug)]
+struct DiskStats {
+    reads: u64,
+    sectors_read: u64,
+    time_reading: u64,
+    writes: u64,
+    sectors_written: u64,
+    time_writing: u64,
+    time_io: u64,
+    weighted_time_io: u64,
+}
+
+impl DiskStats {
+    fn parse(line: &str) -> Option<(String, DiskStats)> {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 14 {
+            return None;
+        }
+
+        let value = |idx: usize| fields[idx].parse::<u64>().unwrap_or(0);
+        let stats = DiskStats {
+            reads: value(3),
+            sectors_read: value(5),
+            time_reading: value(6),
+            writes: value(7),
+            sectors_written: value(9),
+            time_writing: value(10),
+            time_io: value(12),
+            weighted_time_io: value(13),
+        };
+
+        Some((fields[2].to_string(), stats))
+    }
+}
+
+impl BlockDeviceInfo {
+    fn new(name: &str, prev: &DiskStats, curr: &DiskStats, elapsed_secs: f64) -> Self {
+        let reads = curr.reads.saturating_sub(prev.reads) as f64;
+        let writes = curr.writes.saturating_sub(prev.writes) as f64;
+        let time_reading = curr.time_reading.saturating_sub(prev.time_reading) as f64;
+        let time_writing = curr.time_writing.saturating_sub(prev.time_writing) as f64;
+        let elapsed_ms = elapsed_secs * 1000.0;
+
+        let ratio = |value: f64, count: f64| if count > 0.0 { value / count } else { 0.0 };
+
+        BlockDeviceInfo {
+            name: name.to_string(),
+            reads_per_sec: ratio(reads, elapsed_secs),
+            writes_per_sec: ratio(writes, elapsed_secs),
+            read_bytes_per_sec: ratio((curr.sectors_read.saturating_sub(prev.sectors_read) * SECTOR_SIZE) as f64, elapsed_secs),
+            write_bytes_per_sec: ratio((curr.sectors_written.saturating_sub(prev.sectors_written) * SECTOR_SIZE) as f64, elapsed_secs),
+            read_await: ratio(time_reading, reads),
+            write_await: ratio(time_writing, writes),
+            await_ms: ratio(time_reading + time_writing, reads + writes),
+            queue_depth: ratio(curr.weighted_time_io.saturating_sub(prev.weighted_time_io) as f64, elapsed_ms),
+            utilization: (ratio(curr.time_io.saturating_sub(prev.time_io) as f64, elapsed_ms) * 100.0).min(100.0),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(default)]
+pub struct IOConfig {
+    include_partitions: bool,
+    exclude_devices: Vec<String>,
+    exclude_fs_types: Vec<String>,
+}
+
+impl Default for IOConfig {
+    fn default() -> Self {
+        IOConfig {
+            include_partitions: false,
+            exclude_devices: vec![String::from("loop"), String::from("ram"), String::from("zram")],
+            exclude_fs_types: ["tmpfs", "devtmpfs", "overlay", "squashfs", "nfs", "nfs4", "cifs", "smb3", "ceph", "fuse.sshfs", "fuse.glusterfs"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+        }
+    }
+}
+
+impl IOConfig {
+    pub fn get_include_partitions(&self) -> bool {
+        self.include_partitions
+    }
+
+    /// Block device name prefixes skipped by the diskstats collector.
+    pub fn get_exclude_devices(&self) -> &Vec<String> {
+        &self.exclude_devices
+    }
+
+    /// Filesystem types skipped when reporting mounts. Pseudo filesystems
+    /// without blocks (proc, sysfs, cgroup, ...) are always skipped. Network
+    /// filesystems are skipped by default since statvfs blocks while their
+    /// server is unreachable.
+    pub fn get_exclude_fs_types(&self) -> &Vec<String> {
+        &self.exclude_fs_types
+    }
+}
+
+pub static COLLECTOR: CollectorSpec = CollectorSpec {
+    name: "io",
+    section: "io_config",
+    title: "I/O Info",
+    refresh_rate: Duration::from_secs(1),
+    default_options: || to_options(&IOConfig::default()),
+    new: |options, paths| Ok(Box::new(IOInfoCollector::new(&from_options(options)?, paths))),
+};
+
+pub struct IOInfoCollector {
+    include_partitions: bool,
+    exclude_devices: Vec<String>,
+    exclude_fs_types: Vec<String>,
+    prev_stats: HashMap<String, DiskStats>,
+    prev_time: Option<f64>,
+    paths: HostPaths,
+    read_only: HashMap<String, bool>,
+    events: Vec<QueueItem>,
+}
 
 impl MetricCollector for IOInfoCollector {
     fn collect_info(&mut self) -> QueueItem {
-        QueueItem::IO(self.get_io_info())
+        match self.get_io_info() {
+            Ok(io_info) => QueueItem::sample(&COLLECTOR, &io_info),
+            Err(e) => QueueItem::Err(ErrInfo::new(format!("io: {}", e))),
+        }
+    }
+
+    fn collect_events(&mut self) -> Vec<QueueItem> {
+        std::mem::take(&mut self.events)
+    }
+
+    fn reset_counters(&mut self) {
+        self.prev_stats.clear();
+        self.prev_time = None;
     }
 }
 
 impl IOInfoCollector {
-    pub fn new() -> Self {
-        Self { }
+    pub fn new(config: &IOConfig, paths: &HostPaths) -> Self {
+        Self {
+            include_partitions: config.get_include_partitions(),
+            exclude_devices: config.get_exclude_devices().clone(),
+            exclude_fs_types: config.get_exclude_fs_types().clone(),
+            prev_stats: HashMap::new(),
+            prev_time: None,
+            paths: paths.clone(),
+            read_only: HashMap::new(),
+            events: Vec::new(),
+        }
     }
 
-    pub fn get_io_info(&mut self) -> IOInfo {
-        let mut disks_info = Vec::<DiskInfo>::new();
+    pub fn get_io_info(&mut self) -> Result<IOInfo, std::io::Error> {
+        let disks_info = self.get_disks_info()
+            .map_err(|e| std::io::Error::new(e.kind(), format!("failed to read {}: {}", MOUNTINFO_PATH, e)))?;
+        let devices_info = self.get_devices_info()
+            .map_err(|e| std::io::Error::new(e.kind(), format!("failed to read {}: {}", DISKSTATS_PATH, e)))?;
+
+        Ok(IOInfo { disks_info, devices_info })
+    }
+
+    /// Reports mounted filesystems and queues an event when one is remounted read-only,
+    /// which is how ext4 reacts to errors with errors=remount-ro. The mount table and
+    /// the root are those of init, so inside a container with the host's proc_root
+    /// the host's mounts are reported rather than the container's own.
+    // statvfs field types are narrower than u64 on some targets
+    #[allow(clippy::unnecessary_cast)]
+    fn get_disks_info(&mut self) -> Result<Vec<DiskInfo>, std::io::Error> {
+        let mountinfo = fs::read_to_string(self.paths.resolve(MOUNTINFO_PATH))?;
+        let mount_root = self.paths.resolve(MOUNT_ROOT_PATH);
+
+        let mut disks_info = Vec::new();
+        let mut read_only = HashMap::new();
 
-        let disks = Disks::new_with_refreshed_list();
-        for disk in &disks {
-            let disk_info = DiskInfo {
-                name: disk.name().to_str().unwrap().to_string(),
-                mount_point: disk.mount_point().to_str().unwrap().to_string(),
-                total_space: disk.total_space() / (1024 * 1024),
-                available_space: disk.available_space() / (1024 * 1024),
+        for mount in mountinfo.lines().filter_map(MountInfo::parse) {
+            if self.exclude_fs_types.contains(&mount.fs_type) {
+                continue;
+            }
+
+            let stat = match statvfs(&mount_root.join(mount.mount_point.trim_start_matches('/'))) {
+                Ok(stat) => stat,
+                Err(_) => continue,
             };
+            // pseudo filesystems like proc or sysfs have no blocks
+            if stat.f_blocks == 0 {
+                continue;
+            }
+
+            match self.read_only.get(&mount.mount_point) {
+                Some(false) if mount.read_only => {
+                    self.events.push(QueueItem::Event(EventInfo::new(COLLECTOR.name,
+                        format!("filesystem {} ({}) on {} was remounted read-only", mount.mount_point, mount.fs_type, mount.source))));
+                },
+                Some(true) if !mount.read_only => {
+                    self.events.push(QueueItem::Event(EventInfo::new(COLLECTOR.name,
+                        format!("filesystem {} ({}) on {} was remounted read-write", mount.mount_point, mount.fs_type, mount.source))));
+                },
+                _ => (),
+            }
+            read_only.insert(mount.mount_point.clone(), mount.read_only);
 
-            disks_info.push(disk_info);
+            let block_size = stat.f_frsize as u64;
+            disks_info.push(DiskInfo {
+                name: mount.source,
+                mount_point: mount.mount_point,
+                fs_type: mount.fs_type,
+                options: mount.options,
+                read_only: mount.read_only,
+                total_space: stat.f_blocks as u64 * block_size,
+                available_space: stat.f_bavail as u64 * block_size,
+                total_inodes: stat.f_files as u64,
+                free_inodes: stat.f_ffree as u64,
+            });
         }
 
-        IOInfo { disks_info }
+        self.read_only = read_only;
+
+        Ok(disks_info)
     }
-}
\ No newline at end of file
+
+    /// Computes per-device rates since the previous call. Devices seen for
+    /// the first time are only reported from the next call on.
+    fn get_devices_info(&mut self) -> Result<Vec<BlockDeviceInfo>, std::io::Error> {
+        let diskstats = fs::read_to_string(self.paths.resolve(DISKSTATS_PATH))?;
+        let now = self.paths.read_uptime();
+        let elapsed_secs = self.prev_time.map(|prev| elapsed_secs(Some(prev), now));
+
+        let mut devices_info = Vec::new();
+        let mut stats_map = HashMap::new();
+
+        for (name, stats) in diskstats.lines().filter_map(DiskStats::parse) {
+            if !self.is_device_included(&name) {
+                continue;
+            }
+
+            if let (Some(prev), Some(elapsed_secs)) = (self.prev_stats.get(&name), elapsed_secs) {
+                devices_info.push(BlockDeviceInfo::new(&name, prev, &stats, elapsed_secs));
+            }
+            stats_map.insert(name, stats);
+        }
+
+        self.prev_stats = stats_map;
+        self.prev_time = now;
+
+        Ok(devices_info)
+    }
+
+    fn is_device_included(&self, name: &str) -> bool {
+        if self.exclude_devices.iter().any(|prefix| name.starts_with(prefix.as_str())) {
+            return false;
+        }
+
+        self.include_partitions || !self.paths.resolve(SYS_CLASS_BLOCK_PATH).join(name).join("partition").exists()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metric::host::{assert_close, fixture_paths};
+    use crate::queue::event_messages;
+
+    #[test]
+    fn device_rates_between_snapshots() {
+        let mut collector = IOInfoCollector::new(&IOConfig::default(), &fixture_paths("kvm-guest/t0"));
+        assert!(collector.get_devices_info().unwrap().is_empty());
+        collector.paths = fixture_paths("kvm-guest/t1");
+        let devices_info = collector.get_devices_info().unwrap();
+
+        // loop and zram devices are excluded by default
+        let names: Vec<&str> = devices_info.iter().map(|device| device.name.as_str()).collect();
+        assert_eq!(names, ["vda", "vdb"]);
+
+        let vda = &devices_info[0];
+        assert_close(vda.reads_per_sec, 61.0 / 1.71);
+        assert_close(vda.writes_per_sec, 64.0 / 1.71);
+        assert_close(vda.read_bytes_per_sec, 22480.0 * 512.0 / 1.71);
+        assert_close(vda.write_bytes_per_sec, 131072.0 * 512.0 / 1.71);
+        assert_close(vda.read_await, 45.0 / 61.0);
+        assert_close(vda.write_await, 30.0 / 64.0);
+        assert_close(vda.await_ms, 75.0 / 125.0);
+        assert_close(vda.queue_depth, 75.0 / 1710.0);
+        assert_close(vda.utilization, 52.0 * 100.0 / 1710.0);
+
+        let vdb = &devices_info[1];
+        assert_close(vdb.reads_per_sec, 0.0);
+        assert_close(vdb.utilization, 0.0);
+    }
+
+    #[test]
+    fn remount_read_only_between_snapshots() {
+        let mut collector = IOInfoCollector::new(&IOConfig::default(), &fixture_paths("scenarios/bare-metal/t0"));
+        let disks_info = collector.get_disks_info().unwrap();
+        // /boot doesn't resolve to a directory of the fixture, /srv/backup is nfs4
+        let mount_points: Vec<&str> = disks_info.iter().map(|disk| disk.mount_point.as_str()).collect();
+        assert_eq!(mount_points, ["/"]);
+        assert!(!disks_info[0].read_only);
+        assert!(disks_info[0].total_space > 0);
+        assert!(collector.collect_events().is_empty());
+
+        collector.paths = fixture_paths("scenarios/bare-metal/t1");
+        let disks_info = collector.get_disks_info().unwrap();
+        assert!(disks_info[0].read_only);
+        let events = collector.collect_events();
+        assert_eq!(event_messages(&events), ["filesystem / (ext4) on /dev/nvme0n1p2 was remounted read-only"]);
+
+        collector.paths = fixture_paths("scenarios/bare-metal/t0");
+        collector.get_disks_info().unwrap();
+        let events = collector.collect_events();
+        assert_eq!(event_messages(&events), ["filesystem / (ext4) on /dev/nvme0n1p2 was remounted read-write"]);
+    }
+
+    #[test]
+    fn network_filesystems_can_be_included() {
+        let config = IOConfig { exclude_fs_types: Vec::new(), ..IOConfig::default() };
+        let mut collector = IOInfoCollector::new(&config, &fixture_paths("scenarios/bare-metal/t0"));
+        let disks_info = collector.get_disks_info().unwrap();
+        let mount_points: Vec<(&str, &str)> = disks_info.iter().map(|disk| (disk.mount_point.as_str(), disk.fs_type.as_str())).collect();
+        assert_eq!(mount_points, [("/", "ext4"), ("/srv/backup", "nfs4")]);
+    }
+
+    #[test]
+    fn parse_mountinfo_line() {
+        let mount = MountInfo::parse("29 1 254:0 / /mnt/my\\040disk rw,relatime shared:1 - ext4 /dev/vda rw,errors=remount-ro").unwrap();
+        assert_eq!(mount.source, "/dev/vda");
+        assert_eq!(mount.mount_point, "/mnt/my disk");
+        assert_eq!(mount.fs_type, "ext4");
+        assert!(!mount.read_only);
+    }
+}
@@ -0,0 +1,269 @@
+use std::collections::BTreeSet;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::queue::{ErrInfo, EventInfo, QueueItem};
+use crate::metric::{CollectorSpec, MetricCollector};
+use crate::metric::host::HostPaths;
+use crate::sample::{Point, SampleSource, Unit};
+
+
+const OSRELEASE_PATH: &str = "/proc/sys/kernel/osrelease";
+const VERSION_PATH: &str = "/proc/sys/kernel/version";
+const BOOT_ID_PATH: &str = "/proc/sys/kernel/random/boot_id";
+const UPTIME_PATH: &str = "/proc/uptime";
+const TAINTED_PATH: &str = "/proc/sys/kernel/tainted";
+const MODULES_PATH: &str = "/proc/modules";
+
+/// Taint bits as `(bit, flag, description)`, see Documentation/admin-guide/tainted-kernels.rst.
+const TAINT_FLAGS: [(u32, char, &str); 20] = [
+    (0, 'P', "proprietary module loaded"),
+    (1, 'F', "module force loaded"),
+    (2, 'S', "running on an out of specification system"),
+    (3, 'R', "module force unloaded"),
+    (4, 'M', "machine check exception"),
+    (5, 'B', "bad page referenced"),
+    (6, 'U', "taint requested by userspace"),
+    (7, 'D', "kernel died recently (oops or bug)"),
+    (8, 'A', "ACPI table overridden"),
+    (9, 'W', "kernel issued warning"),
+    (10, 'C', "staging driver loaded"),
+    (11, 'I', "platform firmware bug workaround applied"),
+    (12, 'O', "out-of-tree module loaded"),
+    (13, 'E', "unsigned module loaded"),
+    (14, 'L', "soft lockup occurred"),
+    (15, 'K', "kernel live patched"),
+    (16, 'X', "auxiliary taint"),
+    (17, 'T', "built with struct randomization plugin"),
+    (18, 'N', "in-kernel test has been run"),
+    (19, 'J', "userspace used a mutating debug operation"),
+];
+
+/// Decodes a taint mask into "W (kernel issued warning), O (out-of-tree module loaded)".
+fn decode_taint(tainted: u64) -> String {
+    let mut flags: Vec<String> = TAINT_FLAGS
+        .iter()
+        .filter(|(bit, _, _)| tainted & (1 << bit) != 0)
+        .map(|(_, flag, description)| format!("{} ({})", flag, description))
+        .collect();
+    let unknown = tainted & !((1 << TAINT_FLAGS.len()) - 1);
+    if unknown != 0 {
+        flags.push(format!("unknown bits {:#x}", unknown));
+    }
+    flags.join(", ")
+}
+
+/// Formats seconds as "1d 2h 3m 4s".
+fn format_duration(secs: u64) -> String {
+    let (days, hours, minutes, secs) = (secs / 86400, secs % 86400 / 3600, secs % 3600 / 60, secs % 60);
+    if days > 0 {
+        format!("{}d {}h {}m {}s", days, hours, minutes, secs)
+    } else {
+        format!("{}h {}m {}s", hours, minutes, secs)
+    }
+}
+
+pub struct KernelInfo {
+    release: String,
+    version: String,
+    boot_id: String,
+    uptime: f64,
+    /// Sum of the idle time of all cpus.
+    idle: f64,
+    tainted: u64,
+    /// None when the kernel is built without module support.
+    modules: Option<BTreeSet<String>>,
+}
+
+impl Display for KernelInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Release: {}, version: {}", self.release, self.version)?;
+        writeln!(f, "Boot id: {}, uptime: {} ({:.2} s), idle: {:.2} s",
+                 self.boot_id, format_duration(self.uptime as u64), self.uptime, self.idle)?;
+        if self.tainted == 0 {
+            writeln!(f, "Tainted: 0")?;
+        } else {
+            writeln!(f, "Tainted: {}: {}", self.tainted, decode_taint(self.tainted))?;
+        }
+        match &self.modules {
+            Some(modules) => {
+                let modules: Vec<&str> = modules.iter().map(|module| module.as_str()).collect();
+                writeln!(f, "Modules ({}): {}", modules.len(), modules.join(" "))?;
+            },
+            None => writeln!(f, "Modules: not supported")?,
+        }
+        Ok(())
+    }
+}
+
+impl SampleSource for KernelInfo {
+    fn points(&self) -> Vec<Point> {
+        let mut points = vec![
+            // identity as labels of a constant gauge, so a sink can join it with other points
+            Point::gauge("kernel_info", Unit::Count, 1.0)
+                .label("release", &self.release)
+                .label("version", &self.version)
+                .label("boot_id", &self.boot_id),
+            Point::counter("uptime", Unit::Seconds, self.uptime),
+            Point::counter("idle_time", Unit::Seconds, self.idle),
+            Point::gauge("tainted", Unit::Count, self.tainted as f64),
+        ];
+        if let Some(modules) = &self.modules {
+            points.push(Point::gauge("modules", Unit::Count, modules.len() as f64));
+        }
+        points
+    }
+}
+
+fn read_string(path: PathBuf) -> Result<String, std::io::Error> {
+    Ok(fs::read_to_string(path)?.trim().to_string())
+}
+
+/// Module names, the first field of every /proc/modules line.
+fn read_modules(paths: &HostPaths) -> Option<BTreeSet<String>> {
+    let modules = fs::read_to_string(paths.resolve(MODULES_PATH)).ok()?;
+    Some(modules.lines().filter_map(|line| line.split_whitespace().next()).map(|name| name.to_string()).collect())
+}
+
+pub static COLLECTOR: CollectorSpec = CollectorSpec {
+    name: "kernel",
+    section: "kernel_config",
+    title: "Kernel Info",
+    refresh_rate: Duration::from_secs(60),
+    default_options: toml::Table::new,
+    new: |_, paths| Ok(Box::new(KernelInfoCollector::new(paths))),
+};
+
+pub struct KernelInfoCollector {
+    prev_tainted: Option<u64>,
+    prev_modules: Option<BTreeSet<String>>,
+    events: Vec<QueueItem>,
+    paths: HostPaths,
+}
+
+impl MetricCollector for KernelInfoCollector {
+    fn collect_info(&mut self) -> QueueItem {
+        match self.get_kernel_info() {
+            Ok(kernel_info) => QueueItem::sample(&COLLECTOR, &kernel_info),
+            Err(e) => QueueItem::Err(ErrInfo::new(format!("kernel: failed to read kernel identity: {}", e))),
+        }
+    }
+
+    fn collect_events(&mut self) -> Vec<QueueItem> {
+        std::mem::take(&mut self.events)
+    }
+}
+
+impl KernelInfoCollector {
+    pub fn new(paths: &HostPaths) -> Self {
+        Self {
+            prev_tainted: None,
+            prev_modules: None,
+            events: Vec::new(),
+            paths: paths.clone(),
+        }
+    }
+
+    /// Taint and module list changes since the previous call are sent as events.
+    pub fn get_kernel_info(&mut self) -> Result<KernelInfo, std::io::Error> {
+        let read = |path: &str| read_string(self.paths.resolve(path));
+        let uptime = read(UPTIME_PATH)?;
+        let mut uptime = uptime.split_whitespace().map(|v| v.parse::<f64>().unwrap_or(0.0));
+
+        let kernel_info = KernelInfo {
+            release: read(OSRELEASE_PATH)?,
+            version: read(VERSION_PATH)?,
+            boot_id: read(BOOT_ID_PATH).unwrap_or_default(),
+            uptime: uptime.next().unwrap_or(0.0),
+            idle: uptime.next().unwrap_or(0.0),
+            tainted: read(TAINTED_PATH)?.parse::<u64>().unwrap_or(0),
+            modules: read_modules(&self.paths),
+        };
+
+        if let Some(prev_tainted) = self.prev_tainted.replace(kernel_info.tainted) {
+            if prev_tainted != kernel_info.tainted {
+                let new_flags = kernel_info.tainted & !prev_tainted;
+                let mut message = format!("taint changed from {} to {}", prev_tainted, kernel_info.tainted);
+                if new_flags != 0 {
+                    message = format!("{}, new flags: {}", message, decode_taint(new_flags));
+                }
+                self.push_event(message);
+            }
+        }
+
+        if let (Some(prev_modules), Some(modules)) = (self.prev_modules.take(), &kernel_info.modules) {
+            for module in modules.difference(&prev_modules) {
+                self.push_event(format!("module loaded: {}", module));
+            }
+            for module in prev_modules.difference(modules) {
+                self.push_event(format!("module unloaded: {}", module));
+            }
+        }
+        self.prev_modules = kernel_info.modules.clone();
+
+        Ok(kernel_info)
+    }
+
+    fn push_event(&mut self, message: String) {
+        self.events.push(QueueItem::Event(EventInfo::new(COLLECTOR.name, message)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metric::host::fixture_paths;
+    use crate::queue::event_messages;
+
+    #[test]
+    fn kernel_identity_from_fixture() {
+        let mut collector = KernelInfoCollector::new(&fixture_paths("kvm-guest/t0"));
+        let kernel_info = collector.get_kernel_info().unwrap();
+
+        assert_eq!(kernel_info.release, "6.18.44-fc-v130");
+        assert_eq!(kernel_info.version, "#1 SMP PREEMPT_DYNAMIC @0");
+        assert_eq!(kernel_info.boot_id, "fb9e4a87-26e5-4394-9efb-ee56004af11f");
+        assert_eq!(kernel_info.uptime, 2174.67);
+        assert_eq!(kernel_info.tainted, 0);
+        // built without module support
+        assert!(kernel_info.modules.is_none());
+        assert!(collector.collect_events().is_empty());
+    }
+
+    #[test]
+    fn taint_and_module_events_between_snapshots() {
+        let mut collector = KernelInfoCollector::new(&fixture_paths("scenarios/bare-metal/t0"));
+        let kernel_info = collector.get_kernel_info().unwrap();
+        assert_eq!(kernel_info.modules.as_ref().unwrap().len(), 3);
+        assert!(collector.collect_events().is_empty());
+
+        collector.paths = fixture_paths("scenarios/bare-metal/t1");
+        let kernel_info = collector.get_kernel_info().unwrap();
+        assert_eq!(kernel_info.tainted, 4608);
+        let events = collector.collect_events();
+        assert_eq!(event_messages(&events), [
+            "taint changed from 0 to 4608, new flags: W (kernel issued warning), O (out-of-tree module loaded)",
+            "module loaded: nvidia",
+            "module unloaded: e1000e",
+        ]);
+
+        // no events without changes
+        collector.get_kernel_info().unwrap();
+        assert!(collector.collect_events().is_empty());
+    }
+
+    #[test]
+    fn decode_taint_flags() {
+        assert_eq!(decode_taint(0), "");
+        assert_eq!(decode_taint(1 << 9 | 1 << 12), "W (kernel issued warning), O (out-of-tree module loaded)");
+        assert_eq!(decode_taint(1 << 21), "unknown bits 0x200000");
+    }
+
+    #[test]
+    fn format_uptime() {
+        assert_eq!(format_duration(2174), "0h 36m 14s");
+        assert_eq!(format_duration(90061), "1d 1h 1m 1s");
+    }
+}
@@ -0,0 +1,518 @@
+use std::fmt::{Display, Formatter};
+use std::fs::{File, OpenOptions};
+use std::io::{ErrorKind, Read, Seek, SeekFrom};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::AsRawFd;
+use std::thread::sleep;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::{from_options, to_options};
+use crate::queue::{ErrInfo, QueueItem};
+use crate::metric::{CollectorSpec, MetricCollector};
+use crate::sample::{Point, SampleSource, Unit};
+
+
+/// Records are at most CONSOLE_EXT_LOG_MAX bytes, a smaller buffer makes read() fail with EINVAL.
+const KMSG_RECORD_MAX: usize = 8192;
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum KmsgClass {
+    OomKill,
+    HungTask,
+    SoftLockup,
+    HardLockup,
+    Oops,
+    Segfault,
+    FilesystemError,
+    IOError,
+    Other,
+}
+
+pub fn kmsg_class_to_str(class: KmsgClass) -> &'static str {
+    match class {
+        KmsgClass::OomKill => "oom_kill",
+        KmsgClass::HungTask => "hung_task",
+        KmsgClass::SoftLockup => "soft_lockup",
+        KmsgClass::HardLockup => "hard_lockup",
+        KmsgClass::Oops => "oops",
+        KmsgClass::Segfault => "segfault",
+        KmsgClass::FilesystemError => "fs_error",
+        KmsgClass::IOError => "io_error",
+        KmsgClass::Other => "other",
+    }
+}
+
+const KMSG_CLASSES: [KmsgClass; 8] = [
+    KmsgClass::OomKill,
+    KmsgClass::HungTask,
+    KmsgClass::SoftLockup,
+    KmsgClass::HardLockup,
+    KmsgClass::Oops,
+    KmsgClass::Segfault,
+    KmsgClass::FilesystemError,
+    KmsgClass::IOError,
+];
+
+/// Classifies a kernel message by the well known strings printed by the kernel.
+pub fn classify(message: &str) -> KmsgClass {
+    if message.contains("Out of memory: Killed process") || message.starts_with("oom-kill:")
+        || message.contains("Memory cgroup out of memory") {
+        KmsgClass::OomKill
+    } else if message.contains("blocked for more than") {
+        KmsgClass::HungTask
+    } else if message.contains("soft lockup") {
+        KmsgClass::SoftLockup
+    } else if message.contains("hard LOCKUP") {
+        KmsgClass::HardLockup
+    } else if message.starts_with("Oops:") || message.starts_with("kernel BUG at")
+        || message.starts_with("BUG: unable to handle") || message.starts_with("BUG: kernel NULL pointer dereference")
+        || message.starts_with("general protection fault") {
+        KmsgClass::Oops
+    } else if message.contains(" segfault at ") {
+        KmsgClass::Segfault
+    } else if message.starts_with("EXT4-fs error")
+        || (message.starts_with("EXT4-fs") && (message.contains("Remounting filesystem read-only") || message.contains("error count")))
+        || (message.starts_with("XFS (") && (message.contains("I/O error") || message.contains("Corruption") || message.contains("Shutting down filesystem"))) {
+        KmsgClass::FilesystemError
+    } else if message.contains("I/O error") || message.contains("critical medium error") {
+        KmsgClass::IOError
+    } else {
+        KmsgClass::Other
+    }
+}
+
+/// Position of a record in a line printed in several pieces with KERN_CONT,
+/// from the flags field of the record.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+enum KmsgFragment {
+    /// '-', a whole line.
+    Line,
+    /// 'c', the first piece of a line.
+    First,
+    /// '+', a piece continuing the previous record.
+    Continuation,
+}
+
+/// A single /dev/kmsg record, see Documentation/ABI/testing/dev-kmsg.
+pub struct KmsgEvent {
+    facility: u8,
+    priority: u8,
+    sequence: u64,
+    timestamp_us: u64,
+    fragment: KmsgFragment,
+    class: KmsgClass,
+    message: String,
+    properties: Vec<(String, String)>,
+}
+
+impl KmsgEvent {
+    /// Parses "prio,seq,ts_usec,flags[,..];message\n KEY=value\n".
+    pub fn parse(record: &str) -> Option<Self> {
+        let (header, body) = record.split_once(';')?;
+        let mut fields = header.split(',');
+        let prio = fields.next()?.parse::<u32>().ok()?;
+        let sequence = fields.next()?.parse::<u64>().ok()?;
+        let timestamp_us = fields.next()?.parse::<u64>().ok()?;
+        let fragment = match fields.next().and_then(|flags| flags.chars().next()) {
+            Some('c') => KmsgFragment::First,
+            Some('+') => KmsgFragment::Continuation,
+            _ => KmsgFragment::Line,
+        };
+
+        let mut lines = body.lines();
+        let message = unescape(lines.next().unwrap_or_default());
+        // continuation lines carry the device dictionary and start with a space
+        let properties = lines
+            .filter_map(|line| line.strip_prefix(' '))
+            .filter_map(|line| line.split_once('='))
+            .map(|(key, value)| (key.to_string(), unescape(value)))
+            .collect();
+
+        Some(KmsgEvent {
+            facility: (prio >> 3) as u8,
+            priority: (prio & 7) as u8,
+            sequence,
+            timestamp_us,
+            fragment,
+            class: classify(&message),
+            message,
+            properties,
+        })
+    }
+
+    pub fn get_class(&self) -> KmsgClass {
+        self.class
+    }
+
+    /// Joins a continuation record to this one, the line is classified again as a whole.
+    fn append(&mut self, continuation: KmsgEvent) {
+        self.message.push_str(&continuation.message);
+        self.properties.extend(continuation.properties);
+        self.class = classify(&self.message);
+    }
+}
+
+/// Non printable characters are escaped as \xNN by the kernel.
+fn unescape(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(idx) = rest.find("\\x") {
+        result.push_str(&rest[..idx]);
+        let code = rest.get(idx + 2..idx + 4).and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match code {
+            Some(code) => {
+                result.push(code as char);
+                rest = &rest[idx + 4..];
+            }
+            None => {
+                result.push_str("\\x");
+                rest = &rest[idx + 2..];
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+impl Display for KmsgEvent {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[{}.{:06}] {} (facility: {}, priority: {}, seq: {}): {}",
+               self.timestamp_us / 1_000_000, self.timestamp_us % 1_000_000, kmsg_class_to_str(self.class),
+               self.facility, self.priority, self.sequence, self.message)?;
+        for (key, value) in &self.properties {
+            write!(f, " {}={}", key, value)?;
+        }
+        writeln!(f)
+    }
+}
+
+/// Number of classified kernel messages seen since the collector started.
+pub struct KmsgInfo {
+    counts: Vec<(KmsgClass, u64)>,
+    messages: u64,
+}
+
+impl Display for KmsgInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "messages: {}", self.messages)?;
+        for (class, count) in &self.counts {
+            write!(f, ", {}: {}", kmsg_class_to_str(*class), count)?;
+        }
+        writeln!(f)
+    }
+}
+
+impl SampleSource for KmsgInfo {
+    fn points(&self) -> Vec<Point> {
+        let mut points = vec![Point::counter("kmsg_messages", Unit::Count, self.messages as f64)];
+        for (class, count) in &self.counts {
+            points.push(Point::counter("kmsg_class_messages", Unit::Count, *count as f64).label("class", kmsg_class_to_str(*class)));
+        }
+        points
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(default)]
+pub struct KmsgConfig {
+    path: String,
+    replay: bool,
+    report_all: bool,
+}
+
+impl Default for KmsgConfig {
+    fn default() -> Self {
+        KmsgConfig {
+            path: String::from("/dev/kmsg"),
+            replay: false,
+            report_all: false,
+        }
+    }
+}
+
+impl KmsgConfig {
+    pub fn get_path(&self) -> &str {
+        &self.path
+    }
+
+    /// Report messages already in the kernel ring buffer on start.
+    pub fn get_replay(&self) -> bool {
+        self.replay
+    }
+
+    /// Report every kernel message, not only classified ones.
+    pub fn get_report_all(&self) -> bool {
+        self.report_all
+    }
+}
+
+pub static COLLECTOR: CollectorSpec = CollectorSpec {
+    name: "kmsg",
+    section: "kmsg_config",
+    title: "Kernel Log Info",
+    refresh_rate: Duration::from_secs(60),
+    default_options: || to_options(&KmsgConfig::default()),
+    new: |options, _| Ok(Box::new(KmsgInfoCollector::new(&from_options(options)?))),
+};
+
+pub struct KmsgInfoCollector {
+    path: String,
+    replay: bool,
+    report_all: bool,
+    file: Option<File>,
+    failed: bool,
+    counts: Vec<(KmsgClass, u64)>,
+    messages: u64,
+    /// Line being assembled from continuation records, reported once the next
+    /// line starts or the buffer is drained.
+    pending: Option<KmsgEvent>,
+}
+
+impl MetricCollector for KmsgInfoCollector {
+    fn collect_info(&mut self) -> QueueItem {
+        QueueItem::sample(&COLLECTOR, &KmsgInfo { counts: self.counts.clone(), messages: self.messages })
+    }
+
+    fn is_event_driven(&self) -> bool {
+        !self.failed
+    }
+
+    /// Tails the kernel log, classified messages are returned as soon as they are read.
+    fn wait(&mut self, timeout: Duration) -> Vec<QueueItem> {
+        let mut items = Vec::new();
+        if self.file.is_none() && !self.failed {
+            if let Err(e) = self.open() {
+                items.push(QueueItem::Err(ErrInfo::new(format!("kmsg: failed to open {}: {}", self.path, e))));
+                self.failed = true;
+            }
+        }
+
+        let fd = match &self.file {
+            Some(file) => file.as_raw_fd(),
+            None => {
+                sleep(timeout);
+                return items;
+            }
+        };
+
+        let mut poll_fd = libc::pollfd { fd, events: libc::POLLIN, revents: 0 };
+        let timeout_ms = timeout.as_millis().min(libc::c_int::MAX as u128) as libc::c_int;
+        let res = unsafe { libc::poll(&mut poll_fd, 1, timeout_ms) };
+        if res < 0 {
+            let err = std::io::Error::last_os_error();
+            if err.kind() != ErrorKind::Interrupted {
+                items.push(QueueItem::Err(ErrInfo::new(format!("kmsg: failed to poll {}: {}", self.path, err))));
+                self.file = None;
+                self.failed = true;
+            }
+            return items;
+        }
+
+        if poll_fd.revents & libc::POLLIN != 0 {
+            items.extend(self.read_records());
+        }
+
+        items
+    }
+
+    fn reset_counters(&mut self) {
+        for (_, count) in self.counts.iter_mut() {
+            *count = 0;
+        }
+        self.messages = 0;
+    }
+}
+
+impl KmsgInfoCollector {
+    pub fn new(config: &KmsgConfig) -> Self {
+        Self {
+            path: config.get_path().to_string(),
+            replay: config.get_replay(),
+            report_all: config.get_report_all(),
+            file: None,
+            failed: false,
+            counts: KMSG_CLASSES.iter().map(|class| (*class, 0)).collect(),
+            messages: 0,
+            pending: None,
+        }
+    }
+
+    fn open(&mut self) -> Result<(), std::io::Error> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(&self.path)?;
+
+        if !self.replay {
+            // only messages logged from now on
+            file.seek(SeekFrom::End(0))?;
+        }
+
+        self.file = Some(file);
+        Ok(())
+    }
+
+    /// Every read() returns exactly one record, reads until the buffer is drained.
+    fn read_records(&mut self) -> Vec<QueueItem> {
+        let mut items = Vec::new();
+        let mut buf = vec![0u8; KMSG_RECORD_MAX];
+
+        while let Some(file) = self.file.as_mut() {
+            match file.read(&mut buf) {
+                Ok(0) => break,
+                Ok(len) => {
+                    let record = String::from_utf8_lossy(&buf[..len]);
+                    self.add_record(&record, &mut items);
+                },
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                // EPIPE: older records were overwritten before being read, the next read continues
+                Err(e) if e.raw_os_error() == Some(libc::EPIPE) => continue,
+                Err(e) => {
+                    items.push(QueueItem::Err(ErrInfo::new(format!("kmsg: failed to read {}: {}", self.path, e))));
+                    self.file = None;
+                    self.failed = true;
+                },
+            }
+        }
+
+        // nothing more to read, an OOM or oops line isn't held back until the next message
+        if let Some(pending) = self.pending.take() {
+            self.report(pending, &mut items);
+        }
+
+        items
+    }
+
+    /// Continuation records are joined to the line they continue, a '+' record
+    /// without a line to continue is reported on its own.
+    fn add_record(&mut self, record: &str, items: &mut Vec<QueueItem>) {
+        let event = match KmsgEvent::parse(record) {
+            Some(event) => event,
+            None => return,
+        };
+        if event.fragment == KmsgFragment::Continuation {
+            if let Some(pending) = self.pending.as_mut() {
+                pending.append(event);
+                return;
+            }
+        }
+
+        if let Some(pending) = self.pending.take() {
+            self.report(pending, items);
+        }
+        if event.fragment == KmsgFragment::First {
+            self.pending = Some(event);
+        } else {
+            self.report(event, items);
+        }
+    }
+
+    fn report(&mut self, event: KmsgEvent, items: &mut Vec<QueueItem>) {
+        self.messages += 1;
+        if let Some((_, count)) = self.counts.iter_mut().find(|(class, _)| *class == event.get_class()) {
+            *count += 1;
+        }
+        if self.report_all || event.get_class() != KmsgClass::Other {
+            items.push(QueueItem::KernelEvent(event));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn kernel_events(items: &[QueueItem]) -> Vec<&KmsgEvent> {
+        items
+            .iter()
+            .filter_map(|item| match item {
+                QueueItem::KernelEvent(event) => Some(event),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parse_header_and_dictionary() {
+        let record = "3,1204,5132876104,-;sd 2:0:0:0: [sdb] tag#9 Add. Sense: Unrecovered read error\\x0a\n SUBSYSTEM=scsi\n DEVICE=+scsi:2:0:0:0\n";
+        let event = KmsgEvent::parse(record).unwrap();
+        // prio 3 is facility 0 (kern), priority 3 (err)
+        assert_eq!((event.facility, event.priority), (0, 3));
+        assert_eq!((event.sequence, event.timestamp_us), (1204, 5132876104));
+        assert_eq!(event.fragment, KmsgFragment::Line);
+        assert_eq!(event.message, "sd 2:0:0:0: [sdb] tag#9 Add. Sense: Unrecovered read error\n");
+        assert_eq!(event.properties, [
+            (String::from("SUBSYSTEM"), String::from("scsi")),
+            (String::from("DEVICE"), String::from("+scsi:2:0:0:0")),
+        ]);
+
+        // prio 30 is facility 3 (daemon), priority 6 (info)
+        let event = KmsgEvent::parse("30,1205,5132876200,-,caller=T1;systemd[1]: Started Session 3.").unwrap();
+        assert_eq!((event.facility, event.priority), (3, 6));
+        assert!(event.properties.is_empty());
+        assert!(KmsgEvent::parse("no header").is_none());
+    }
+
+    #[test]
+    fn classify_messages() {
+        let messages = [
+            ("Oops: 0002 [#1] PREEMPT SMP NOPTI", KmsgClass::Oops),
+            ("BUG: kernel NULL pointer dereference, address: 0000000000000008", KmsgClass::Oops),
+            ("kernel BUG at mm/slub.c:4321!", KmsgClass::Oops),
+            ("Out of memory: Killed process 2342 (java) total-vm:8123456kB, anon-rss:4012345kB", KmsgClass::OomKill),
+            ("oom-kill:constraint=CONSTRAINT_MEMCG,nodemask=(null),task=java,pid=2342,uid=1000", KmsgClass::OomKill),
+            ("INFO: task kworker/u8:2:123 blocked for more than 122 seconds.", KmsgClass::HungTask),
+            ("watchdog: BUG: soft lockup - CPU#2 stuck for 23s! [kworker/2:1:88]", KmsgClass::SoftLockup),
+            ("Watchdog detected hard LOCKUP on cpu 3", KmsgClass::HardLockup),
+            ("java[2342]: segfault at 8 ip 00007f0c sp 00007ffd error 4 in libjvm.so", KmsgClass::Segfault),
+            ("EXT4-fs error (device sda1): ext4_find_entry:1455: inode #2: comm ls: reading directory lblock 0", KmsgClass::FilesystemError),
+            ("blk_update_request: I/O error, dev sdb, sector 2048 op 0x0:(READ)", KmsgClass::IOError),
+            ("I/O error, dev nvme0n1, sector 12345 op 0x1:(WRITE) flags 0x800 phys_seg 1 prio class 0", KmsgClass::IOError),
+            ("systemd[1]: Started Session 3.", KmsgClass::Other),
+        ];
+        for (message, class) in messages {
+            assert_eq!(classify(message), class, "{}", message);
+        }
+    }
+
+    #[test]
+    fn continuation_records_are_joined() {
+        let mut collector = KmsgInfoCollector::new(&KmsgConfig::default());
+        let mut items = Vec::new();
+        collector.add_record("4,100,1000,c;Out of memory: ", &mut items);
+        collector.add_record("4,101,1001,+;Killed process 2342 (java)", &mut items);
+        assert!(items.is_empty());
+
+        // the next line completes the pending one
+        collector.add_record("6,102,1002,-;systemd[1]: Started Session 3.", &mut items);
+        let events = kernel_events(&items);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].message, "Out of memory: Killed process 2342 (java)");
+        assert_eq!(events[0].sequence, 100);
+        assert_eq!(events[0].get_class(), KmsgClass::OomKill);
+        assert_eq!(collector.messages, 2);
+
+        // a continuation without a first piece is a line of its own
+        let mut items = Vec::new();
+        collector.add_record("4,103,1003,+;I/O error, dev sdb, sector 2048", &mut items);
+        assert_eq!(kernel_events(&items)[0].get_class(), KmsgClass::IOError);
+        assert_eq!(collector.messages, 3);
+    }
+
+    #[test]
+    fn pending_line_is_reported_when_drained() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/scenarios/kmsg/oom");
+        let config = KmsgConfig { path: path.to_string_lossy().to_string(), replay: true, ..KmsgConfig::default() };
+        let mut collector = KmsgInfoCollector::new(&config);
+        collector.open().unwrap();
+        let items = collector.read_records();
+        let events = kernel_events(&items);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].get_class(), KmsgClass::OomKill);
+        assert!(collector.pending.is_none());
+    }
+}
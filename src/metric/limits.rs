@@ -0,0 +1,305 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::{from_options, to_options};
+use crate::queue::{ErrInfo, QueueItem};
+use crate::metric::{CollectorSpec, MetricCollector};
+use crate::metric::host::HostPaths;
+use crate::sample::{Point, SampleSource, Unit};
+
+
+const FILE_NR_PATH: &str = "/proc/sys/fs/file-nr";
+const FILE_MAX_PATH: &str = "/proc/sys/fs/file-max";
+const LOADAVG_PATH: &str = "/proc/loadavg";
+const PID_MAX_PATH: &str = "/proc/sys/kernel/pid_max";
+const THREADS_MAX_PATH: &str = "/proc/sys/kernel/threads-max";
+const INOTIFY_MAX_INSTANCES_PATH: &str = "/proc/sys/fs/inotify/max_user_instances";
+const INOTIFY_MAX_WATCHES_PATH: &str = "/proc/sys/fs/inotify/max_user_watches";
+const CONNTRACK_COUNT_PATH: &str = "/proc/sys/net/netfilter/nf_conntrack_count";
+const CONNTRACK_MAX_PATH: &str = "/proc/sys/net/netfilter/nf_conntrack_max";
+const ARP_PATH: &str = "/proc/net/arp";
+const NEIGH_GC_THRESH3_PATH: &str = "/proc/sys/net/ipv4/neigh/default/gc_thresh3";
+const PROC_PATH: &str = "/proc";
+
+#[derive(Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Debug)]
+pub enum LimitLevel {
+    Ok,
+    Warning,
+    Critical,
+}
+
+pub struct LimitsInfo {
+    limits_info: Vec<LimitInfo>,
+}
+
+/// Usage of a single kernel table against its limit.
+pub struct LimitInfo {
+    name: String,
+    used: u64,
+    limit: u64,
+    percent: f64,
+    level: LimitLevel,
+}
+
+impl LimitInfo {
+    pub fn get_level(&self) -> LimitLevel {
+        self.level
+    }
+}
+
+impl Display for LimitsInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for limit_info in &self.limits_info {
+            writeln!(f, "{}", limit_info)?;
+        }
+        Ok(())
+    }
+}
+
+impl SampleSource for LimitsInfo {
+    fn points(&self) -> Vec<Point> {
+        let mut points = Vec::new();
+        for limit_info in &self.limits_info {
+            let values = [
+                Point::gauge("limit_used", Unit::Count, limit_info.used as f64),
+                Point::gauge("limit_max", Unit::Count, limit_info.limit as f64),
+                Point::gauge("limit_usage", Unit::Percent, limit_info.percent),
+            ];
+            points.extend(values.into_iter().map(|point| point.label("limit", &limit_info.name)));
+        }
+        points
+    }
+}
+
+impl Display for LimitInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, " {}: {}/{} ({:.2}%)", self.name, self.used, self.limit, self.percent)?;
+        match self.level {
+            LimitLevel::Ok => Ok(()),
+            LimitLevel::Warning => write!(f, " [WARNING]"),
+            LimitLevel::Critical => write!(f, " [CRITICAL]"),
+        }
+    }
+}
+
+fn read_u64(path: PathBuf) -> Option<u64> {
+    fs::read_to_string(path).ok()?.trim().parse::<u64>().ok()
+}
+
+/// Number of allocated file handles, the second field of file-nr (free handles)
+/// has been always 0 since 2.6.
+fn read_file_handles(paths: &HostPaths) -> Option<u64> {
+    let file_nr = fs::read_to_string(paths.resolve(FILE_NR_PATH)).ok()?;
+    let mut fields = file_nr.split_whitespace().map(|v| v.parse::<u64>().unwrap_or(0));
+    let allocated = fields.next()?;
+    let free = fields.next().unwrap_or(0);
+    Some(allocated.saturating_sub(free))
+}
+
+/// Number of tasks from the "running/total" field of /proc/loadavg. Every thread
+/// takes a pid, so it is checked against both pid_max and threads-max.
+fn read_tasks(paths: &HostPaths) -> Option<u64> {
+    let loadavg = fs::read_to_string(paths.resolve(LOADAVG_PATH)).ok()?;
+    let (_, total) = loadavg.split_whitespace().nth(3)?.split_once('/')?;
+    total.parse::<u64>().ok()
+}
+
+/// Neighbour entries of the IPv4 table, IPv6 neighbours are only exposed over netlink.
+fn read_arp_entries(paths: &HostPaths) -> Option<u64> {
+    let arp = fs::read_to_string(paths.resolve(ARP_PATH)).ok()?;
+    Some(arp.lines().skip(1).filter(|line| !line.trim().is_empty()).count() as u64)
+}
+
+/// Inotify instances and watches of the busiest user, as the limits are per user.
+/// An instance inherited by several processes is counted once per process.
+#[derive(Default)]
+struct InotifyUsage {
+    instances: u64,
+    watches: u64,
+}
+
+fn read_inotify_usage(paths: &HostPaths) -> InotifyUsage {
+    let mut users: HashMap<u32, InotifyUsage> = HashMap::new();
+    let entries = match fs::read_dir(paths.resolve(PROC_PATH)) {
+        Ok(entries) => entries,
+        Err(_) => return InotifyUsage::default(),
+    };
+
+    for entry in entries.flatten() {
+        let pid_path = entry.path();
+        if !entry.file_name().to_string_lossy().chars().all(|c| c.is_ascii_digit()) {
+            continue;
+        }
+        let uid = match entry.metadata() {
+            Ok(metadata) => metadata.uid(),
+            Err(_) => continue,
+        };
+        // processes of other users are not readable without privileges
+        let fds = match fs::read_dir(pid_path.join("fd")) {
+            Ok(fds) => fds,
+            Err(_) => continue,
+        };
+
+        for fd in fds.flatten() {
+            let is_inotify = fs::read_link(fd.path())
+                .map(|target| target == Path::new("anon_inode:inotify"))
+                .unwrap_or(false);
+            if !is_inotify {
+                continue;
+            }
+            let watches = fs::read_to_string(pid_path.join("fdinfo").join(fd.file_name()))
+                .map(|fdinfo| fdinfo.lines().filter(|line| line.starts_with("inotify ")).count() as u64)
+                .unwrap_or(0);
+
+            let usage = users.entry(uid).or_default();
+            usage.instances += 1;
+            usage.watches += watches;
+        }
+    }
+
+    InotifyUsage {
+        instances: users.values().map(|usage| usage.instances).max().unwrap_or(0),
+        watches: users.values().map(|usage| usage.watches).max().unwrap_or(0),
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(default)]
+pub struct LimitsConfig {
+    warning_percent: f64,
+    critical_percent: f64,
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        LimitsConfig {
+            warning_percent: 80.0,
+            critical_percent: 95.0,
+        }
+    }
+}
+
+impl LimitsConfig {
+    /// Usage in percent of the limit from which a table is reported as a warning.
+    pub fn get_warning_percent(&self) -> f64 {
+        self.warning_percent
+    }
+
+    /// Usage in percent of the limit from which a table is reported as critical.
+    pub fn get_critical_percent(&self) -> f64 {
+        self.critical_percent
+    }
+}
+
+pub static COLLECTOR: CollectorSpec = CollectorSpec {
+    name: "limits",
+    section: "limits_config",
+    title: "Limits Info",
+    refresh_rate: Duration::from_secs(10),
+    default_options: || to_options(&LimitsConfig::default()),
+    new: |options, paths| Ok(Box::new(LimitsInfoCollector::new(&from_options(options)?, paths))),
+};
+
+pub struct LimitsInfoCollector {
+    warning_percent: f64,
+    critical_percent: f64,
+    paths: HostPaths,
+}
+
+impl MetricCollector for LimitsInfoCollector {
+    fn collect_info(&mut self) -> QueueItem {
+        match self.get_limits_info() {
+            Ok(limits_info) => QueueItem::sample(&COLLECTOR, &limits_info),
+            Err(e) => QueueItem::Err(ErrInfo::new(format!("limits: failed to read kernel limits: {}", e))),
+        }
+    }
+}
+
+impl LimitsInfoCollector {
+    pub fn new(config: &LimitsConfig, paths: &HostPaths) -> Self {
+        Self {
+            warning_percent: config.get_warning_percent(),
+            critical_percent: config.get_critical_percent(),
+            paths: paths.clone(),
+        }
+    }
+
+    /// Tables that are not available (e.g. conntrack without nf_conntrack loaded)
+    /// are skipped.
+    pub fn get_limits_info(&mut self) -> Result<LimitsInfo, std::io::Error> {
+        // file-nr is always present, anything else is optional
+        fs::metadata(self.paths.resolve(FILE_NR_PATH))?;
+
+        let paths = &self.paths;
+        let read = |path: &str| read_u64(paths.resolve(path));
+        let tasks = read_tasks(paths);
+        let inotify = read_inotify_usage(paths);
+        let limits = [
+            ("file_handles", read_file_handles(paths), read(FILE_MAX_PATH)),
+            ("pids", tasks, read(PID_MAX_PATH)),
+            ("threads", tasks, read(THREADS_MAX_PATH)),
+            ("inotify_instances", Some(inotify.instances), read(INOTIFY_MAX_INSTANCES_PATH)),
+            ("inotify_watches", Some(inotify.watches), read(INOTIFY_MAX_WATCHES_PATH)),
+            ("conntrack", read(CONNTRACK_COUNT_PATH), read(CONNTRACK_MAX_PATH)),
+            ("neighbours", read_arp_entries(paths), read(NEIGH_GC_THRESH3_PATH)),
+        ];
+
+        let limits_info = limits
+            .into_iter()
+            .filter_map(|(name, used, limit)| match (used, limit) {
+                (Some(used), Some(limit)) if limit > 0 => Some(self.get_limit_info(name, used, limit)),
+                _ => None,
+            })
+            .collect();
+
+        Ok(LimitsInfo { limits_info })
+    }
+
+    fn get_limit_info(&self, name: &str, used: u64, limit: u64) -> LimitInfo {
+        let percent = used as f64 * 100.0 / limit as f64;
+        let level = if percent >= self.critical_percent {
+            LimitLevel::Critical
+        } else if percent >= self.warning_percent {
+            LimitLevel::Warning
+        } else {
+            LimitLevel::Ok
+        };
+
+        LimitInfo { name: name.to_string(), used, limit, percent, level }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metric::host::fixture_paths;
+
+    #[test]
+    fn limits_from_fixture() {
+        let mut collector = LimitsInfoCollector::new(&LimitsConfig::default(), &fixture_paths("kvm-guest/t0"));
+        let limits_info = collector.get_limits_info().unwrap();
+
+        let limit = |name: &str| limits_info.limits_info.iter().find(|limit| limit.name == name).map(|limit| (limit.used, limit.limit));
+        assert_eq!(limit("file_handles"), Some((294, 613832)));
+        assert_eq!(limit("pids"), Some((72, 32768)));
+        assert_eq!(limit("threads"), Some((72, 48005)));
+        assert_eq!(limit("inotify_instances"), Some((0, 128)));
+        assert_eq!(limit("conntrack"), Some((0, 262144)));
+        assert_eq!(limit("neighbours"), Some((1, 1024)));
+        assert!(limits_info.limits_info.iter().all(|limit| limit.get_level() == LimitLevel::Ok));
+    }
+
+    #[test]
+    fn limit_levels() {
+        let collector = LimitsInfoCollector::new(&LimitsConfig::default(), &fixture_paths("kvm-guest/t0"));
+        assert_eq!(collector.get_limit_info("pids", 79, 100).get_level(), LimitLevel::Ok);
+        assert_eq!(collector.get_limit_info("pids", 80, 100).get_level(), LimitLevel::Warning);
+        assert_eq!(collector.get_limit_info("pids", 95, 100).get_level(), LimitLevel::Critical);
+    }
+}
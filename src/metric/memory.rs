@@ -1,49 +1,169 @@
+use std::collections::HashMap;
 use std::fmt::{Display, Formatter};
-
-use sysinfo::System;
+use std::fs;
+use std::time::Duration;
 
 use crate::queue::{ErrInfo, QueueItem};
-use crate::metric::MetricCollector;
+use crate::metric::{CollectorSpec, MetricCollector};
+use crate::metric::host::HostPaths;
+use crate::sample::{Point, SampleSource, Unit};
+
 
+const MEMINFO_PATH: &str = "/proc/meminfo";
 
+/// Memory usage from /proc/meminfo, in bytes.
 pub struct MemoryInfo {
     total: u64,
     used: u64,
     free: u64,
+    available: u64,
+    buffers: u64,
+    cached: u64,
+    dirty: u64,
+    writeback: u64,
+    slab_reclaimable: u64,
+    slab_unreclaimable: u64,
+    shmem: u64,
+    anon_pages: u64,
+    committed_as: u64,
+    commit_limit: u64,
+    swap_total: u64,
+    swap_free: u64,
 }
 
 impl Display for MemoryInfo {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        writeln!(f, "Total memory: {} MB, Used: {} MB, Free: {} MB", self.total, self.used, self.free)?;
+        let mb = |value: u64| value / (1024 * 1024);
+        writeln!(f, "Total memory: {} MB, Used: {} MB, Free: {} MB, Available: {} MB", mb(self.total), mb(self.used), mb(self.free), mb(self.available))?;
+        writeln!(f, "Buffers: {} MB, Cached: {} MB, Dirty: {} MB, Writeback: {} MB, Shmem: {} MB, AnonPages: {} MB",
+                 mb(self.buffers), mb(self.cached), mb(self.dirty), mb(self.writeback), mb(self.shmem), mb(self.anon_pages))?;
+        writeln!(f, "Slab reclaimable: {} MB, Slab unreclaimable: {} MB", mb(self.slab_reclaimable), mb(self.slab_unreclaimable))?;
+        writeln!(f, "Committed: {} MB of {} MB commit limit", mb(self.committed_as), mb(self.commit_limit))?;
+        writeln!(f, "Swap total: {} MB, Used: {} MB, Free: {} MB", mb(self.swap_total), mb(self.swap_total.saturating_sub(self.swap_free)), mb(self.swap_free))?;
         Ok(())
     }
 }
 
+impl SampleSource for MemoryInfo {
+    fn points(&self) -> Vec<Point> {
+        let values = [
+            ("memory_total", self.total), ("memory_used", self.used), ("memory_free", self.free),
+            ("memory_available", self.available), ("memory_buffers", self.buffers), ("memory_cached", self.cached),
+            ("memory_dirty", self.dirty), ("memory_writeback", self.writeback),
+            ("memory_slab_reclaimable", self.slab_reclaimable), ("memory_slab_unreclaimable", self.slab_unreclaimable),
+            ("memory_shmem", self.shmem), ("memory_anon_pages", self.anon_pages),
+            ("memory_committed", self.committed_as), ("memory_commit_limit", self.commit_limit),
+            ("swap_total", self.swap_total), ("swap_free", self.swap_free),
+        ];
+        values.into_iter().map(|(name, value)| Point::gauge(name, Unit::Bytes, value as f64)).collect()
+    }
+}
+
+impl MemoryInfo {
+    fn parse(meminfo: &str) -> Self {
+        let values: HashMap<&str, u64> = meminfo
+            .lines()
+            .filter_map(|line| {
+                let (key, value) = line.split_once(':')?;
+                let value = value.split_whitespace().next()?.parse::<u64>().ok()?;
+                Some((key, value))
+            })
+            .collect();
+        // values are in kB
+        let value = |key: &str| values.get(key).copied().unwrap_or(0) * 1024;
+
+        let total = value("MemTotal");
+        let available = value("MemAvailable");
+
+        MemoryInfo {
+            total,
+            used: total.saturating_sub(available),
+            free: value("MemFree"),
+            available,
+            buffers: value("Buffers"),
+            cached: value("Cached"),
+            dirty: value("Dirty"),
+            writeback: value("Writeback"),
+            slab_reclaimable: value("SReclaimable"),
+            slab_unreclaimable: value("SUnreclaim"),
+            shmem: value("Shmem"),
+            anon_pages: value("AnonPages"),
+            committed_as: value("Committed_AS"),
+            commit_limit: value("CommitLimit"),
+            swap_total: value("SwapTotal"),
+            swap_free: value("SwapFree"),
+        }
+    }
+}
+
+pub static COLLECTOR: CollectorSpec = CollectorSpec {
+    name: "memory",
+    section: "memory_config",
+    title: "Memory Info",
+    refresh_rate: Duration::from_secs(1),
+    default_options: toml::Table::new,
+    new: |_, paths| Ok(Box::new(MemoryInfoCollector::new(paths))),
+};
+
 pub struct MemoryInfoCollector {
-    sys: System,
+    paths: HostPaths,
 }
 
 impl MetricCollector for MemoryInfoCollector {
     fn collect_info(&mut self) -> QueueItem {
-        QueueItem::Memory(self.get_memory_info())
+        match self.get_memory_info() {
+            Ok(memory_info) => QueueItem::sample(&COLLECTOR, &memory_info),
+            Err(e) => QueueItem::Err(ErrInfo::new(format!("memory: failed to read {}: {}", MEMINFO_PATH, e))),
+        }
+    }
+}
+
+impl Default for MemoryInfoCollector {
+    fn default() -> Self {
+        Self::new(&HostPaths::default())
     }
 }
 
 impl MemoryInfoCollector {
-    pub fn new() -> Self {
+    pub fn new(paths: &HostPaths) -> Self {
         Self {
-            sys: System::new_all(),
+            paths: paths.clone(),
         }
     }
 
-    pub fn get_memory_info(&mut self) -> MemoryInfo {
-        self.sys.refresh_all();
+    pub fn get_memory_info(&mut self) -> Result<MemoryInfo, std::io::Error> {
+        let meminfo = fs::read_to_string(self.paths.resolve(MEMINFO_PATH))?;
 
-        MemoryInfo {
-            total: self.sys.total_memory() / (1024 * 1024),
-            used: self.sys.used_memory() / (1024 * 1024),
-            free: self.sys.free_memory() / (1024 * 1024),
-        }
+        Ok(MemoryInfo::parse(&meminfo))
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metric::host::fixture_paths;
+    use crate::sample::find_point;
+
+    #[test]
+    fn meminfo_from_fixture() {
+        let memory_info = MemoryInfoCollector::new(&fixture_paths("kvm-guest/t0")).get_memory_info().unwrap();
+
+        assert_eq!(memory_info.total, 6158152 * 1024);
+        assert_eq!(memory_info.free, 3324336 * 1024);
+        assert_eq!(memory_info.available, 5618324 * 1024);
+        assert_eq!(memory_info.used, (6158152 - 5618324) * 1024);
+        assert_eq!(memory_info.buffers, 65344 * 1024);
+        assert_eq!(memory_info.cached, 2415552 * 1024);
+        assert!(memory_info.to_string().starts_with("Total memory: 6013 MB, Used: 527 MB, Free: 3246 MB, Available: 5486 MB\n"));
+
+        let points = memory_info.points();
+        let available = find_point(&points, "memory_available", &[]).unwrap();
+        assert_eq!(available.get_unit(), Unit::Bytes);
+        assert_eq!(available.get_value(), 5618324.0 * 1024.0);
+    }
+
+    #[test]
+    fn missing_meminfo_is_an_error() {
+        assert!(MemoryInfoCollector::new(&fixture_paths("missing")).get_memory_info().is_err());
+    }
+}
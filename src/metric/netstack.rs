@@ -0,0 +1,291 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::time::Duration;
+
+use crate::queue::{ErrInfo, QueueItem};
+use crate::metric::{CollectorSpec, MetricCollector};
+use crate::metric::host::{elapsed_secs, HostPaths};
+use crate::sample::{Point, SampleSource, Unit};
+
+
+const SNMP_PATH: &str = "/proc/net/snmp";
+const NETSTAT_PATH: &str = "/proc/net/netstat";
+const SOCKSTAT_PATH: &str = "/proc/net/sockstat";
+const TCP_PATHS: [&str; 2] = ["/proc/net/tcp", "/proc/net/tcp6"];
+
+/// TCP states by their `st` value in /proc/net/tcp, starting at 1 (include/net/tcp_states.h).
+const TCP_STATES: [&str; 12] = [
+    "established", "syn_sent", "syn_recv", "fin_wait1", "fin_wait2", "time_wait",
+    "close", "close_wait", "last_ack", "listen", "closing", "new_syn_recv",
+];
+
+/// Protocol counters reported as per second rates, as `(proto, field, label)`.
+const RATE_COUNTERS: [(&str, &str, &str); 14] = [
+    ("Tcp", "ActiveOpens", "tcp_active_opens"),
+    ("Tcp", "PassiveOpens", "tcp_passive_opens"),
+    ("Tcp", "AttemptFails", "tcp_attempt_fails"),
+    ("Tcp", "EstabResets", "tcp_estab_resets"),
+    ("Tcp", "OutRsts", "tcp_out_resets"),
+    ("Tcp", "RetransSegs", "tcp_retrans_segs"),
+    ("Tcp", "InErrs", "tcp_in_errors"),
+    ("TcpExt", "ListenOverflows", "tcp_listen_overflows"),
+    ("TcpExt", "ListenDrops", "tcp_listen_drops"),
+    ("TcpExt", "TCPSynRetrans", "tcp_syn_retrans"),
+    ("TcpExt", "TCPTimeouts", "tcp_timeouts"),
+    ("Udp", "NoPorts", "udp_no_ports"),
+    ("Udp", "InErrors", "udp_in_errors"),
+    ("Udp", "RcvbufErrors", "udp_rcvbuf_errors"),
+];
+
+pub struct NetStackInfo {
+    tcp_established: u64,
+    tcp_inuse: u64,
+    tcp_orphan: u64,
+    tcp_time_wait: u64,
+    tcp_alloc: u64,
+    udp_inuse: u64,
+    sockets_used: u64,
+    /// IPv4 and IPv6 sockets by state, in TCP_STATES order.
+    tcp_states: [u64; TCP_STATES.len()],
+    rates: Vec<(&'static str, f64)>,
+}
+
+impl Display for NetStackInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Sockets used: {}, tcp established: {}, tcp inuse: {}, tcp orphan: {}, tcp time_wait: {}, tcp alloc: {}, udp inuse: {}",
+                 self.sockets_used, self.tcp_established, self.tcp_inuse, self.tcp_orphan, self.tcp_time_wait, self.tcp_alloc, self.udp_inuse)?;
+        let tcp_states: Vec<String> = TCP_STATES.iter().zip(self.tcp_states)
+            .filter(|(_, count)| *count > 0)
+            .map(|(state, count)| format!("{}: {}", state, count))
+            .collect();
+        writeln!(f, "TCP sockets by state: {}", tcp_states.join(", "))?;
+        for (label, rate) in &self.rates {
+            writeln!(f, " {}: {:.2}/s", label, rate)?;
+        }
+        Ok(())
+    }
+}
+
+impl SampleSource for NetStackInfo {
+    fn points(&self) -> Vec<Point> {
+        let mut points = vec![
+            Point::gauge("sockets_used", Unit::Count, self.sockets_used as f64),
+            Point::gauge("tcp_established", Unit::Count, self.tcp_established as f64),
+            Point::gauge("tcp_inuse", Unit::Count, self.tcp_inuse as f64),
+            Point::gauge("tcp_orphan", Unit::Count, self.tcp_orphan as f64),
+            Point::gauge("tcp_time_wait", Unit::Count, self.tcp_time_wait as f64),
+            Point::gauge("tcp_alloc", Unit::Count, self.tcp_alloc as f64),
+            Point::gauge("udp_inuse", Unit::Count, self.udp_inuse as f64),
+        ];
+        for (state, count) in TCP_STATES.iter().zip(self.tcp_states) {
+            points.push(Point::gauge("tcp_sockets", Unit::Count, count as f64).label("state", *state));
+        }
+        points.extend(self.rates.iter().map(|(label, rate)| Point::rate(*label, Unit::Count, *rate)));
+        points
+    }
+}
+
+/// Parses the header/value line pairs of /proc/net/snmp and /proc/net/netstat
+/// into `Proto.Field` keys.
+fn parse_protocol_counters(content: &str, counters: &mut HashMap<String, i64>) {
+    let lines: Vec<&str> = content.lines().collect();
+    for pair in lines.chunks(2) {
+        if pair.len() < 2 {
+            break;
+        }
+        let (proto, header) = match pair[0].split_once(':') {
+            Some(header) => header,
+            None => continue,
+        };
+        let values = match pair[1].split_once(':') {
+            Some((_, values)) => values,
+            None => continue,
+        };
+
+        // some fields like Tcp MaxConn are signed
+        for (field, value) in header.split_whitespace().zip(values.split_whitespace()) {
+            counters.insert(format!("{}.{}", proto, field), value.parse::<i64>().unwrap_or(0));
+        }
+    }
+}
+
+/// Parses "TCP: inuse 4 orphan 0 tw 0 alloc 4 mem 0" lines into `PROTO.key` keys.
+fn parse_sockstat(content: &str) -> HashMap<String, u64> {
+    let mut sockstat = HashMap::new();
+    for line in content.lines() {
+        let (proto, fields) = match line.split_once(':') {
+            Some(line) => line,
+            None => continue,
+        };
+        let fields: Vec<&str> = fields.split_whitespace().collect();
+        for pair in fields.chunks(2) {
+            if let [key, value] = pair {
+                sockstat.insert(format!("{}.{}", proto, key), value.parse::<u64>().unwrap_or(0));
+            }
+        }
+    }
+    sockstat
+}
+
+/// Counts the `st` column of a /proc/net/tcp or tcp6 table.
+fn count_tcp_states(content: &str, tcp_states: &mut [u64; TCP_STATES.len()]) {
+    // the first line is the header
+    for line in content.lines().skip(1) {
+        let state = line.split_whitespace().nth(3).and_then(|st| usize::from_str_radix(st, 16).ok());
+        if let Some(count) = state.and_then(|state| state.checked_sub(1)).and_then(|idx| tcp_states.get_mut(idx)) {
+            *count += 1;
+        }
+    }
+}
+
+pub static COLLECTOR: CollectorSpec = CollectorSpec {
+    name: "netstack",
+    section: "netstack_config",
+    title: "Network Stack Info",
+    refresh_rate: Duration::from_secs(1),
+    default_options: toml::Table::new,
+    new: |_, paths| Ok(Box::new(NetStackInfoCollector::new(paths))),
+};
+
+pub struct NetStackInfoCollector {
+    prev_counters: HashMap<String, i64>,
+    prev_time: Option<f64>,
+    paths: HostPaths,
+}
+
+impl MetricCollector for NetStackInfoCollector {
+    fn collect_info(&mut self) -> QueueItem {
+        match self.get_netstack_info() {
+            Ok(netstack_info) => QueueItem::sample(&COLLECTOR, &netstack_info),
+            Err(e) => QueueItem::Err(ErrInfo::new(format!("netstack: failed to read protocol stats: {}", e))),
+        }
+    }
+
+    fn reset_counters(&mut self) {
+        self.prev_counters.clear();
+        self.prev_time = None;
+    }
+}
+
+impl NetStackInfoCollector {
+    pub fn new(paths: &HostPaths) -> Self {
+        Self {
+            prev_counters: HashMap::new(),
+            prev_time: None,
+            paths: paths.clone(),
+        }
+    }
+
+    /// Rates are computed since the previous call and reported as zero on the first one.
+    /// Socket states are counted from the full socket tables, tcp6 is missing without IPv6.
+    pub fn get_netstack_info(&mut self) -> Result<NetStackInfo, std::io::Error> {
+        let mut counters = HashMap::new();
+        parse_protocol_counters(&fs::read_to_string(self.paths.resolve(SNMP_PATH))?, &mut counters);
+        // TcpExt is missing in some network namespaces
+        if let Ok(netstat) = fs::read_to_string(self.paths.resolve(NETSTAT_PATH)) {
+            parse_protocol_counters(&netstat, &mut counters);
+        }
+        let sockstat = parse_sockstat(&fs::read_to_string(self.paths.resolve(SOCKSTAT_PATH))?);
+        let mut tcp_states = [0; TCP_STATES.len()];
+        for path in TCP_PATHS {
+            if let Ok(tcp) = fs::read_to_string(self.paths.resolve(path)) {
+                count_tcp_states(&tcp, &mut tcp_states);
+            }
+        }
+
+        let now = self.paths.read_uptime();
+        let elapsed_secs = elapsed_secs(self.prev_time, now);
+
+        let mut rates = Vec::new();
+        for (proto, field, label) in RATE_COUNTERS {
+            let key = format!("{}.{}", proto, field);
+            let value = match counters.get(&key) {
+                Some(value) => *value,
+                None => continue,
+            };
+            let prev = self.prev_counters.get(&key).copied().unwrap_or(value);
+            let rate = if elapsed_secs > 0.0 { (value - prev).max(0) as f64 / elapsed_secs } else { 0.0 };
+            rates.push((label, rate));
+        }
+
+        let sock = |key: &str| sockstat.get(key).copied().unwrap_or(0);
+        let netstack_info = NetStackInfo {
+            tcp_established: counters.get("Tcp.CurrEstab").copied().unwrap_or(0).max(0) as u64,
+            tcp_inuse: sock("TCP.inuse"),
+            tcp_orphan: sock("TCP.orphan"),
+            tcp_time_wait: sock("TCP.tw"),
+            tcp_alloc: sock("TCP.alloc"),
+            udp_inuse: sock("UDP.inuse"),
+            sockets_used: sock("sockets.used"),
+            tcp_states,
+            rates,
+        };
+
+        self.prev_counters = counters;
+        self.prev_time = now;
+
+        Ok(netstack_info)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metric::host::{assert_close, fixture_paths};
+    use crate::sample::find_point;
+
+    #[test]
+    fn protocol_rates_between_snapshots() {
+        let mut collector = NetStackInfoCollector::new(&fixture_paths("kvm-guest/t0"));
+        let netstack_info = collector.get_netstack_info().unwrap();
+        assert_eq!(netstack_info.tcp_established, 2);
+        assert_eq!(netstack_info.tcp_inuse, 4);
+        assert_eq!(netstack_info.tcp_alloc, 4);
+        assert_eq!(netstack_info.sockets_used, 16);
+        assert_eq!(netstack_info.rates.len(), RATE_COUNTERS.len());
+
+        collector.paths = fixture_paths("kvm-guest/t1");
+        let netstack_info = collector.get_netstack_info().unwrap();
+        let rate = |label: &str| netstack_info.rates.iter().find(|(l, _)| *l == label).map(|(_, rate)| *rate).unwrap();
+        assert_close(rate("tcp_active_opens"), 1.0 / 1.71);
+        assert_close(rate("tcp_attempt_fails"), 1.0 / 1.71);
+        assert_close(rate("tcp_passive_opens"), 0.0);
+        assert_close(rate("udp_no_ports"), 0.0);
+    }
+
+    #[test]
+    fn socket_states_and_udp_errors_between_snapshots() {
+        let mut collector = NetStackInfoCollector::new(&fixture_paths("scenarios/bare-metal/t0"));
+        let netstack_info = collector.get_netstack_info().unwrap();
+        let tcp_state = |state: &str| netstack_info.tcp_states[TCP_STATES.iter().position(|s| *s == state).unwrap()];
+        // one listening and one established socket in each of tcp and tcp6
+        assert_eq!(tcp_state("listen"), 2);
+        assert_eq!(tcp_state("established"), 2);
+        assert_eq!(netstack_info.tcp_states.iter().sum::<u64>(), 4);
+
+        collector.paths = fixture_paths("scenarios/bare-metal/t1");
+        let netstack_info = collector.get_netstack_info().unwrap();
+        let rate = |label: &str| netstack_info.rates.iter().find(|(l, _)| *l == label).map(|(_, rate)| *rate).unwrap();
+        assert_close(rate("tcp_passive_opens"), 10.0 / 2.5);
+        assert_close(rate("udp_no_ports"), 6.0 / 2.5);
+        assert_close(rate("udp_in_errors"), 3.0 / 2.5);
+        assert_close(rate("udp_rcvbuf_errors"), 3.0 / 2.5);
+
+        let points = netstack_info.points();
+        let listen = find_point(&points, "tcp_sockets", &[("state", "listen")]).unwrap();
+        assert_eq!(listen.get_value(), 2.0);
+        assert_eq!(find_point(&points, "tcp_sockets", &[("state", "time_wait")]).unwrap().get_value(), 0.0);
+    }
+
+    #[test]
+    fn count_tcp_table_states() {
+        let table = "  sl  local_address rem_address   st tx_queue rx_queue\n   \
+                     0: 00000000:0016 00000000:0000 0A 00000000:00000000\n   \
+                     1: 0F02000A:0016 0202000A:D4B6 06 00000000:00000000\n   \
+                     2: 0F02000A:0016 0202000A:D4B7 0C 00000000:00000000\n";
+        let mut tcp_states = [0; TCP_STATES.len()];
+        count_tcp_states(table, &mut tcp_states);
+        assert_eq!(tcp_states, [0, 0, 0, 0, 0, 1, 0, 0, 0, 1, 0, 1]);
+    }
+}
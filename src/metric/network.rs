@@ -0,0 +1,301 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::{from_options, to_options};
+use crate::queue::{ErrInfo, QueueItem};
+use crate::metric::{CollectorSpec, MetricCollector};
+use crate::metric::host::{elapsed_secs, HostPaths};
+use crate::sample::{Point, SampleSource, Unit};
+
+
+const NET_DEV_PATH: &str = "/proc/net/dev";
+const SYS_CLASS_NET_PATH: &str = "/sys/class/net";
+
+pub struct NetworkInfo {
+    interfaces_info: Vec<InterfaceInfo>,
+}
+
+/// Interface traffic between two /proc/net/dev samples, per second.
+pub struct InterfaceInfo {
+    name: String,
+    state: String,
+    speed: Option<u64>,
+    rx_bytes: f64,
+    rx_packets: f64,
+    rx_errors: f64,
+    rx_drops: f64,
+    tx_bytes: f64,
+    tx_packets: f64,
+    tx_errors: f64,
+    tx_drops: f64,
+}
+
+impl Display for NetworkInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for interface_info in &self.interfaces_info {
+            writeln!(f, "{}", interface_info)?;
+        }
+        Ok(())
+    }
+}
+
+impl SampleSource for NetworkInfo {
+    fn points(&self) -> Vec<Point> {
+        let mut points = Vec::new();
+        for interface in &self.interfaces_info {
+            let mut values = vec![
+                Point::rate("net_rx_bytes", Unit::Bytes, interface.rx_bytes),
+                Point::rate("net_rx_packets", Unit::Count, interface.rx_packets),
+                Point::rate("net_rx_errors", Unit::Count, interface.rx_errors),
+                Point::rate("net_rx_drops", Unit::Count, interface.rx_drops),
+                Point::rate("net_tx_bytes", Unit::Bytes, interface.tx_bytes),
+                Point::rate("net_tx_packets", Unit::Count, interface.tx_packets),
+                Point::rate("net_tx_errors", Unit::Count, interface.tx_errors),
+                Point::rate("net_tx_drops", Unit::Count, interface.tx_drops),
+            ];
+            // operstate is "up" for links that can pass packets
+            values.push(Point::gauge("net_up", Unit::Count, if interface.state == "up" { 1.0 } else { 0.0 }));
+            if let Some(speed) = interface.speed {
+                // link speed is in Mb/s
+                values.push(Point::gauge("net_speed", Unit::BitsPerSecond, speed as f64 * 1_000_000.0));
+            }
+            points.extend(values.into_iter().map(|point| point.label("interface", &interface.name)));
+        }
+        points
+    }
+}
+
+impl Display for InterfaceInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let speed = match self.speed {
+            Some(speed) => format!("{} Mb/s", speed),
+            None => String::from("unknown"),
+        };
+        write!(f, "interface: {}, state: {}, speed: {}, rx: {:.2} kB/s {:.2} pkt/s {:.2} err/s {:.2} drop/s, tx: {:.2} kB/s {:.2} pkt/s {:.2} err/s {:.2} drop/s",
+               self.name, self.state, speed,
+               self.rx_bytes / 1024.0, self.rx_packets, self.rx_errors, self.rx_drops,
+               self.tx_bytes / 1024.0, self.tx_packets, self.tx_errors, self.tx_drops)
+    }
+}
+
+/// Counters of a single /proc/net/dev line.
+#[derive(Clone, Copy, Default, Debug)]
+struct InterfaceStats {
+    rx_bytes: u64,
+    rx_packets: u64,
+    rx_errors: u64,
+    rx_drops: u64,
+    tx_bytes: u64,
+    tx_packets: u64,
+    tx_errors: u64,
+    tx_drops: u64,
+}
+
+impl InterfaceStats {
+    fn parse(line: &str) -> Option<(String, InterfaceStats)> {
+        let (name, counters) = line.split_once(':')?;
+        let values: Vec<u64> = counters.split_whitespace().map(|v| v.parse::<u64>().unwrap_or(0)).collect();
+        if values.len() < 16 {
+            return None;
+        }
+
+        let stats = InterfaceStats {
+            rx_bytes: values[0],
+            rx_packets: values[1],
+            rx_errors: values[2],
+            rx_drops: values[3],
+            tx_bytes: values[8],
+            tx_packets: values[9],
+            tx_errors: values[10],
+            tx_drops: values[11],
+        };
+
+        Some((name.trim().to_string(), stats))
+    }
+}
+
+impl InterfaceInfo {
+    fn new(name: &str, prev: &InterfaceStats, curr: &InterfaceStats, elapsed_secs: f64, sys_path: &Path) -> Self {
+        let rate = |prev: u64, curr: u64| {
+            if elapsed_secs > 0.0 {
+                curr.saturating_sub(prev) as f64 / elapsed_secs
+            } else {
+                0.0
+            }
+        };
+
+        let state = fs::read_to_string(sys_path.join("operstate"))
+            .map(|s| s.trim().to_string())
+            .unwrap_or_else(|_| String::from("unknown"));
+        // speed is not readable (EINVAL) or -1 for virtual and down links
+        let speed = fs::read_to_string(sys_path.join("speed"))
+            .ok()
+            .and_then(|s| s.trim().parse::<i64>().ok())
+            .filter(|speed| *speed > 0)
+            .map(|speed| speed as u64);
+
+        InterfaceInfo {
+            name: name.to_string(),
+            state,
+            speed,
+            rx_bytes: rate(prev.rx_bytes, curr.rx_bytes),
+            rx_packets: rate(prev.rx_packets, curr.rx_packets),
+            rx_errors: rate(prev.rx_errors, curr.rx_errors),
+            rx_drops: rate(prev.rx_drops, curr.rx_drops),
+            tx_bytes: rate(prev.tx_bytes, curr.tx_bytes),
+            tx_packets: rate(prev.tx_packets, curr.tx_packets),
+            tx_errors: rate(prev.tx_errors, curr.tx_errors),
+            tx_drops: rate(prev.tx_drops, curr.tx_drops),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(default)]
+pub struct NetworkConfig {
+    exclude_interfaces: Vec<String>,
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        NetworkConfig {
+            exclude_interfaces: vec![String::from("lo")],
+        }
+    }
+}
+
+impl NetworkConfig {
+    /// Interface name prefixes skipped by the network collector.
+    pub fn get_exclude_interfaces(&self) -> &Vec<String> {
+        &self.exclude_interfaces
+    }
+}
+
+pub static COLLECTOR: CollectorSpec = CollectorSpec {
+    name: "net",
+    section: "network_config",
+    title: "Network Info",
+    refresh_rate: Duration::from_secs(1),
+    default_options: || to_options(&NetworkConfig::default()),
+    new: |options, paths| Ok(Box::new(NetworkInfoCollector::new(&from_options(options)?, paths))),
+};
+
+pub struct NetworkInfoCollector {
+    exclude_interfaces: Vec<String>,
+    prev_stats: HashMap<String, InterfaceStats>,
+    prev_time: Option<f64>,
+    paths: HostPaths,
+}
+
+impl MetricCollector for NetworkInfoCollector {
+    fn collect_info(&mut self) -> QueueItem {
+        match self.get_network_info() {
+            Ok(network_info) => QueueItem::sample(&COLLECTOR, &network_info),
+            Err(e) => QueueItem::Err(ErrInfo::new(format!("net: failed to read {}: {}", NET_DEV_PATH, e))),
+        }
+    }
+
+    fn reset_counters(&mut self) {
+        self.prev_stats.clear();
+        self.prev_time = None;
+    }
+}
+
+impl NetworkInfoCollector {
+    pub fn new(config: &NetworkConfig, paths: &HostPaths) -> Self {
+        Self {
+            exclude_interfaces: config.get_exclude_interfaces().clone(),
+            prev_stats: HashMap::new(),
+            prev_time: None,
+            paths: paths.clone(),
+        }
+    }
+
+    /// Computes per-interface rates since the previous call. Interfaces seen
+    /// for the first time are only reported from the next call on.
+    pub fn get_network_info(&mut self) -> Result<NetworkInfo, std::io::Error> {
+        let net_dev = fs::read_to_string(self.paths.resolve(NET_DEV_PATH))?;
+        let now = self.paths.read_uptime();
+        let elapsed_secs = self.prev_time.map(|prev| elapsed_secs(Some(prev), now));
+
+        let mut interfaces_info = Vec::new();
+        let mut stats_map = HashMap::new();
+
+        // the first two lines are column headers
+        for (name, stats) in net_dev.lines().skip(2).filter_map(InterfaceStats::parse) {
+            if self.exclude_interfaces.iter().any(|prefix| name.starts_with(prefix.as_str())) {
+                continue;
+            }
+
+            if let (Some(prev), Some(elapsed_secs)) = (self.prev_stats.get(&name), elapsed_secs) {
+                interfaces_info.push(InterfaceInfo::new(&name, prev, &stats, elapsed_secs, &self.paths.resolve(SYS_CLASS_NET_PATH).join(&name)));
+            }
+            stats_map.insert(name, stats);
+        }
+
+        self.prev_stats = stats_map;
+        self.prev_time = now;
+
+        Ok(NetworkInfo { interfaces_info })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metric::host::{assert_close, fixture_paths};
+    use crate::sample::find_point;
+
+    #[test]
+    fn interface_rates_between_snapshots() {
+        let mut collector = NetworkInfoCollector::new(&NetworkConfig::default(), &fixture_paths("kvm-guest/t0"));
+        assert!(collector.get_network_info().unwrap().interfaces_info.is_empty());
+        collector.paths = fixture_paths("kvm-guest/t1");
+        let network_info = collector.get_network_info().unwrap();
+
+        // lo is excluded by default
+        let names: Vec<&str> = network_info.interfaces_info.iter().map(|interface| interface.name.as_str()).collect();
+        assert_eq!(names, ["ifb0", "ifb1", "eth0"]);
+
+        let eth0 = &network_info.interfaces_info[2];
+        assert_eq!(eth0.state, "up");
+        // virtio reports -1 when the speed is unknown
+        assert_eq!(eth0.speed, None);
+        assert_close(eth0.rx_bytes, 96.0 / 1.71);
+        assert_close(eth0.rx_packets, 2.0 / 1.71);
+        assert_close(eth0.tx_bytes, 116.0 / 1.71);
+        assert_close(eth0.tx_packets, 2.0 / 1.71);
+        assert_close(eth0.rx_errors + eth0.rx_drops + eth0.tx_errors + eth0.tx_drops, 0.0);
+
+        let points = network_info.points();
+        assert!(points.iter().all(|point| point.get_label("state").is_none()));
+        assert_eq!(find_point(&points, "net_up", &[("interface", "eth0")]).unwrap().get_value(), 1.0);
+        assert_eq!(find_point(&points, "net_up", &[("interface", "ifb0")]).unwrap().get_value(), 0.0);
+        assert!(find_point(&points, "net_speed", &[("interface", "eth0")]).is_none());
+    }
+
+    #[test]
+    fn link_speed_is_a_gauge() {
+        let interface = InterfaceInfo {
+            name: String::from("eno1"),
+            state: String::from("up"),
+            speed: Some(1000),
+            rx_bytes: 0.0,
+            rx_packets: 0.0,
+            rx_errors: 0.0,
+            rx_drops: 0.0,
+            tx_bytes: 0.0,
+            tx_packets: 0.0,
+            tx_errors: 0.0,
+            tx_drops: 0.0,
+        };
+        let points = NetworkInfo { interfaces_info: vec![interface] }.points();
+        let speed = find_point(&points, "net_speed", &[("interface", "eno1")]).unwrap();
+        assert_eq!(speed.to_string(), "net_speed{interface=\"eno1\"} 1000000000 (gauge bits_per_second)");
+    }
+}
@@ -0,0 +1,388 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::{from_options, to_options};
+use crate::queue::{ErrInfo, QueueItem};
+use crate::metric::{CollectorSpec, MetricCollector};
+use crate::metric::host::{elapsed_secs, HostPaths};
+use crate::sample::{Point, SampleSource, Unit};
+
+
+const HUGEPAGES_PATH: &str = "/sys/kernel/mm/hugepages";
+const NODES_PATH: &str = "/sys/devices/system/node";
+const BUDDYINFO_PATH: &str = "/proc/buddyinfo";
+
+pub struct NumaInfo {
+    hugepages_info: Vec<HugePagesInfo>,
+    nodes_info: Vec<NodeInfo>,
+    zones_info: Vec<ZoneInfo>,
+    fragmentation_order: usize,
+}
+
+impl Display for NumaInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Hugepages:")?;
+        for hugepages_info in &self.hugepages_info {
+            writeln!(f, "{}", hugepages_info)?;
+        }
+        writeln!(f, "Nodes:")?;
+        for node_info in &self.nodes_info {
+            writeln!(f, "{}", node_info)?;
+        }
+        writeln!(f, "Zones (fragmentation index for order {}):", self.fragmentation_order)?;
+        for zone_info in &self.zones_info {
+            writeln!(f, "{}", zone_info)?;
+        }
+        Ok(())
+    }
+}
+
+impl SampleSource for NumaInfo {
+    fn points(&self) -> Vec<Point> {
+        let mut points = Vec::new();
+        for hugepages_info in &self.hugepages_info {
+            let values = [
+                Point::gauge("hugepages_total", Unit::Pages, hugepages_info.total as f64),
+                Point::gauge("hugepages_free", Unit::Pages, hugepages_info.free as f64),
+                Point::gauge("hugepages_reserved", Unit::Pages, hugepages_info.reserved as f64),
+                Point::gauge("hugepages_surplus", Unit::Pages, hugepages_info.surplus as f64),
+            ];
+            points.extend(values.into_iter().map(|point| point.label("size", format!("{}kB", hugepages_info.size_kb))));
+        }
+        for node_info in &self.nodes_info {
+            let values = [
+                Point::gauge("node_memory_total", Unit::Bytes, node_info.total as f64),
+                Point::gauge("node_memory_free", Unit::Bytes, node_info.free as f64),
+                Point::rate("numa_hit", Unit::Pages, node_info.numa_hit),
+                Point::rate("numa_miss", Unit::Pages, node_info.numa_miss),
+                Point::rate("numa_foreign", Unit::Pages, node_info.numa_foreign),
+                Point::rate("numa_other_node", Unit::Pages, node_info.other_node),
+            ];
+            points.extend(values.into_iter().map(|point| point.label("node", &node_info.name)));
+        }
+        for zone_info in &self.zones_info {
+            let values = [
+                Point::gauge("zone_free", Unit::Pages, zone_info.free_pages as f64),
+                Point::gauge("zone_fragmentation_index", Unit::Ratio, zone_info.fragmentation_index)
+                    .label("order", self.fragmentation_order.to_string()),
+            ];
+            points.extend(values.into_iter().map(|point| point.label("node", &zone_info.node).label("zone", &zone_info.zone)));
+        }
+        points
+    }
+}
+
+/// Pool of a single hugepage size, in pages.
+pub struct HugePagesInfo {
+    size_kb: u64,
+    total: u64,
+    free: u64,
+    reserved: u64,
+    surplus: u64,
+}
+
+impl HugePagesInfo {
+    /// Reads a /sys/kernel/mm/hugepages/hugepages-<size>kB directory.
+    fn read(path: &Path) -> Option<HugePagesInfo> {
+        let name = path.file_name()?.to_string_lossy().to_string();
+        let size_kb = name.strip_prefix("hugepages-")?.strip_suffix("kB")?.parse::<u64>().ok()?;
+
+        Some(HugePagesInfo {
+            size_kb,
+            total: read_u64(&path.join("nr_hugepages"))?,
+            free: read_u64(&path.join("free_hugepages")).unwrap_or(0),
+            reserved: read_u64(&path.join("resv_hugepages")).unwrap_or(0),
+            surplus: read_u64(&path.join("surplus_hugepages")).unwrap_or(0),
+        })
+    }
+}
+
+impl Display for HugePagesInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, " {} kB: total: {}, free: {}, reserved: {}, surplus: {}",
+               self.size_kb, self.total, self.free, self.reserved, self.surplus)
+    }
+}
+
+/// Memory of a NUMA node in bytes, with numastat rates in pages per second.
+pub struct NodeInfo {
+    name: String,
+    total: u64,
+    free: u64,
+    numa_hit: f64,
+    numa_miss: f64,
+    numa_foreign: f64,
+    other_node: f64,
+}
+
+impl Display for NodeInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, " {}: total: {} MB, free: {} MB, numa_hit: {:.2}/s, numa_miss: {:.2}/s, numa_foreign: {:.2}/s, other_node: {:.2}/s",
+               self.name, self.total / (1024 * 1024), self.free / (1024 * 1024), self.numa_hit, self.numa_miss, self.numa_foreign, self.other_node)
+    }
+}
+
+/// Free blocks of a zone from /proc/buddyinfo.
+pub struct ZoneInfo {
+    node: String,
+    zone: String,
+    free_pages: u64,
+    /// Unusable free space index: the share of free memory that cannot serve an
+    /// allocation of the configured order, 0 means no fragmentation.
+    fragmentation_index: f64,
+}
+
+impl ZoneInfo {
+    /// Parses "Node 0, zone   Normal  11815   6305   1046 ..." where every value is
+    /// the number of free blocks of 2^order pages.
+    fn parse(line: &str, order: usize) -> Option<ZoneInfo> {
+        let (node, rest) = line.split_once(',')?;
+        let mut fields = rest.split_whitespace();
+        if fields.next()? != "zone" {
+            return None;
+        }
+        let zone = fields.next()?.to_string();
+        let blocks: Vec<u64> = fields.map(|v| v.parse::<u64>().unwrap_or(0)).collect();
+
+        let free_pages: u64 = blocks.iter().enumerate().map(|(i, count)| count << i).sum();
+        let usable_pages: u64 = blocks.iter().enumerate().skip(order).map(|(i, count)| count << i).sum();
+        let fragmentation_index = if free_pages > 0 {
+            (free_pages - usable_pages) as f64 / free_pages as f64
+        } else {
+            0.0
+        };
+
+        Some(ZoneInfo {
+            node: node.trim().replace("Node ", "node"),
+            zone,
+            free_pages,
+            fragmentation_index,
+        })
+    }
+}
+
+impl Display for ZoneInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, " {} {}: free pages: {}, fragmentation index: {:.3}",
+               self.node, self.zone, self.free_pages, self.fragmentation_index)
+    }
+}
+
+fn read_u64(path: &Path) -> Option<u64> {
+    fs::read_to_string(path).ok()?.trim().parse::<u64>().ok()
+}
+
+/// Entries of `path` matching `prefix`, sorted by name.
+fn dir_entries(path: &Path, prefix: &str) -> Vec<PathBuf> {
+    let mut entries: Vec<PathBuf> = match fs::read_dir(path) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_name().to_string_lossy().starts_with(prefix))
+            .map(|entry| entry.path())
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    entries.sort();
+    entries
+}
+
+/// Parses "Node 0 MemFree: 3404740 kB" lines of a node meminfo, in kB.
+fn parse_node_meminfo(meminfo: &str) -> HashMap<String, u64> {
+    meminfo
+        .lines()
+        .filter_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            let key = key.split_whitespace().last()?;
+            Some((key.to_string(), value.split_whitespace().next()?.parse::<u64>().ok()?))
+        })
+        .collect()
+}
+
+/// Parses "numa_miss 0" lines of a node numastat.
+fn parse_numastat(numastat: &str) -> HashMap<String, u64> {
+    numastat
+        .lines()
+        .filter_map(|line| {
+            let (key, value) = line.split_once(' ')?;
+            Some((key.to_string(), value.trim().parse::<u64>().ok()?))
+        })
+        .collect()
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(default)]
+pub struct NumaConfig {
+    fragmentation_order: usize,
+}
+
+impl Default for NumaConfig {
+    fn default() -> Self {
+        NumaConfig {
+            fragmentation_order: 9,
+        }
+    }
+}
+
+impl NumaConfig {
+    /// Allocation order the fragmentation index is computed for, 9 is a 2 MB
+    /// huge page with 4 kB pages.
+    pub fn get_fragmentation_order(&self) -> usize {
+        self.fragmentation_order
+    }
+}
+
+pub static COLLECTOR: CollectorSpec = CollectorSpec {
+    name: "numa",
+    section: "numa_config",
+    title: "Numa Info",
+    refresh_rate: Duration::from_secs(10),
+    default_options: || to_options(&NumaConfig::default()),
+    new: |options, paths| Ok(Box::new(NumaInfoCollector::new(&from_options(options)?, paths))),
+};
+
+pub struct NumaInfoCollector {
+    fragmentation_order: usize,
+    prev_numastat: HashMap<String, HashMap<String, u64>>,
+    prev_time: Option<f64>,
+    paths: HostPaths,
+}
+
+impl MetricCollector for NumaInfoCollector {
+    fn collect_info(&mut self) -> QueueItem {
+        match self.get_numa_info() {
+            Ok(numa_info) => QueueItem::sample(&COLLECTOR, &numa_info),
+            Err(e) => QueueItem::Err(ErrInfo::new(format!("numa: failed to read {}: {}", BUDDYINFO_PATH, e))),
+        }
+    }
+
+    fn reset_counters(&mut self) {
+        self.prev_numastat.clear();
+        self.prev_time = None;
+    }
+}
+
+impl NumaInfoCollector {
+    pub fn new(config: &NumaConfig, paths: &HostPaths) -> Self {
+        Self {
+            fragmentation_order: config.get_fragmentation_order(),
+            prev_numastat: HashMap::new(),
+            prev_time: None,
+            paths: paths.clone(),
+        }
+    }
+
+    /// Rates are computed since the previous call and reported as zero on the first one.
+    /// Kernels without NUMA support report no nodes.
+    pub fn get_numa_info(&mut self) -> Result<NumaInfo, std::io::Error> {
+        let buddyinfo = fs::read_to_string(self.paths.resolve(BUDDYINFO_PATH))?;
+        let zones_info = buddyinfo
+            .lines()
+            .filter_map(|line| ZoneInfo::parse(line, self.fragmentation_order))
+            .collect();
+
+        let hugepages_info = dir_entries(&self.paths.resolve(HUGEPAGES_PATH), "hugepages-")
+            .iter()
+            .filter_map(|path| HugePagesInfo::read(path))
+            .collect();
+
+        let now = self.paths.read_uptime();
+        let elapsed_secs = elapsed_secs(self.prev_time, now);
+
+        let mut nodes_info = Vec::new();
+        let mut numastat_map = HashMap::new();
+        for node_path in dir_entries(&self.paths.resolve(NODES_PATH), "node") {
+            let name = node_path.file_name().unwrap_or_default().to_string_lossy().to_string();
+            if name.strip_prefix("node").is_none_or(|id| id.parse::<u32>().is_err()) {
+                continue;
+            }
+            let meminfo = parse_node_meminfo(&fs::read_to_string(node_path.join("meminfo")).unwrap_or_default());
+            let numastat = parse_numastat(&fs::read_to_string(node_path.join("numastat")).unwrap_or_default());
+
+            let prev_numastat = self.prev_numastat.get(&name);
+            let rate = |key: &str| {
+                let value = numastat.get(key).copied().unwrap_or(0);
+                let prev = prev_numastat.and_then(|prev| prev.get(key)).copied().unwrap_or(value);
+                if elapsed_secs > 0.0 { value.saturating_sub(prev) as f64 / elapsed_secs } else { 0.0 }
+            };
+
+            nodes_info.push(NodeInfo {
+                name: name.clone(),
+                total: meminfo.get("MemTotal").copied().unwrap_or(0) * 1024,
+                free: meminfo.get("MemFree").copied().unwrap_or(0) * 1024,
+                numa_hit: rate("numa_hit"),
+                numa_miss: rate("numa_miss"),
+                numa_foreign: rate("numa_foreign"),
+                other_node: rate("other_node"),
+            });
+            numastat_map.insert(name, numastat);
+        }
+
+        self.prev_numastat = numastat_map;
+        self.prev_time = now;
+
+        Ok(NumaInfo { hugepages_info, nodes_info, zones_info, fragmentation_order: self.fragmentation_order })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metric::host::{assert_close, fixture_paths};
+    use crate::sample::find_point;
+
+    #[test]
+    fn numa_between_snapshots() {
+        let mut collector = NumaInfoCollector::new(&NumaConfig::default(), &fixture_paths("kvm-guest/t0"));
+        let numa_info = collector.get_numa_info().unwrap();
+        assert_eq!(numa_info.nodes_info[0].free, 3324360 * 1024);
+        assert_eq!(numa_info.nodes_info[0].numa_hit, 0.0);
+
+        collector.paths = fixture_paths("kvm-guest/t1");
+        let numa_info = collector.get_numa_info().unwrap();
+
+        let sizes: Vec<u64> = numa_info.hugepages_info.iter().map(|hugepages| hugepages.size_kb).collect();
+        assert_eq!(sizes, [1048576, 2048]);
+        let hugepages = &numa_info.hugepages_info[1];
+        assert_eq!((hugepages.total, hugepages.free, hugepages.reserved, hugepages.surplus), (0, 0, 0, 0));
+
+        assert_eq!(numa_info.nodes_info.len(), 1);
+        let node = &numa_info.nodes_info[0];
+        assert_eq!(node.name, "node0");
+        assert_eq!(node.total, 6158152 * 1024);
+        assert_eq!(node.free, 3323900 * 1024);
+        // 1.71 s between the snapshots, a single node never misses
+        assert_close(node.numa_hit, (9455837.0 - 9427446.0) / 1.71);
+        assert_close(node.numa_miss, 0.0);
+        assert_close(node.numa_foreign, 0.0);
+        assert_close(node.other_node, 0.0);
+
+        let zones: Vec<&str> = numa_info.zones_info.iter().map(|zone| zone.zone.as_str()).collect();
+        assert_eq!(zones, ["DMA", "DMA32", "Normal"]);
+        let normal = &numa_info.zones_info[2];
+        assert_eq!(normal.node, "node0");
+        // 55388 free pages, 10240 of them in blocks of order 9 or more
+        assert_eq!(normal.free_pages, 55388);
+        assert_close(normal.fragmentation_index, 45148.0 / 55388.0);
+        assert_close(numa_info.zones_info[1].fragmentation_index, 675.0 / 771747.0);
+
+        let points = numa_info.points();
+        let point = find_point(&points, "zone_fragmentation_index", &[("zone", "Normal"), ("order", "9")]).unwrap();
+        assert_close(point.get_value(), normal.fragmentation_index);
+        assert!(find_point(&points, "hugepages_free", &[("size", "2048kB")]).is_some());
+    }
+
+    #[test]
+    fn fragmentation_index_of_order() {
+        let line = "Node 0, zone   Normal      4      2      1";
+        // 4 + 2 * 2 + 1 * 4 = 12 free pages
+        assert_close(ZoneInfo::parse(line, 0).unwrap().fragmentation_index, 0.0);
+        assert_close(ZoneInfo::parse(line, 1).unwrap().fragmentation_index, 4.0 / 12.0);
+        assert_close(ZoneInfo::parse(line, 2).unwrap().fragmentation_index, 8.0 / 12.0);
+        assert!(ZoneInfo::parse("Node 0, something", 0).is_none());
+    }
+}
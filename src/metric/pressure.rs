@@ -0,0 +1,401 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::AsRawFd;
+use std::thread::sleep;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::{from_options, to_options};
+use crate::queue::{ErrInfo, EventInfo, QueueItem};
+use crate::metric::{CollectorSpec, MetricCollector};
+use crate::metric::host::{elapsed_secs, HostPaths};
+use crate::sample::{Point, SampleSource, Unit};
+
+
+const PRESSURE_PATH: &str = "/proc/pressure";
+const PRESSURE_RESOURCES: [&str; 3] = ["cpu", "memory", "io"];
+
+pub struct PressureInfo {
+    resources_info: Vec<ResourcePressure>,
+}
+
+/// Stall averages of one /proc/pressure file.
+pub struct ResourcePressure {
+    resource: String,
+    some: PressureStats,
+    full: Option<PressureStats>,
+}
+
+/// One `some`/`full` line, averages in percent and total stall time in microseconds.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct PressureStats {
+    avg10: f32,
+    avg60: f32,
+    avg300: f32,
+    total: u64,
+    /// Share of the time since the previous collection spent stalled, from the
+    /// increase of `total`.
+    stalled: f64,
+}
+
+impl Display for PressureInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for resource_info in &self.resources_info {
+            writeln!(f, "{}", resource_info)?;
+        }
+        Ok(())
+    }
+}
+
+impl SampleSource for PressureInfo {
+    fn points(&self) -> Vec<Point> {
+        let mut points = Vec::new();
+        for resource_info in &self.resources_info {
+            let stats = std::iter::once(("some", &resource_info.some)).chain(resource_info.full.as_ref().map(|full| ("full", full)));
+            for (kind, stats) in stats {
+                let values = [
+                    Point::gauge("pressure_avg10", Unit::Percent, stats.avg10 as f64),
+                    Point::gauge("pressure_avg60", Unit::Percent, stats.avg60 as f64),
+                    Point::gauge("pressure_avg300", Unit::Percent, stats.avg300 as f64),
+                    Point::counter("pressure_stall_time", Unit::Microseconds, stats.total as f64),
+                    Point::gauge("pressure_stalled", Unit::Percent, stats.stalled),
+                ];
+                points.extend(values.into_iter().map(|point| point.label("resource", &resource_info.resource).label("kind", kind)));
+            }
+        }
+        points
+    }
+}
+
+impl Display for ResourcePressure {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: some {}", self.resource, self.some)?;
+        if let Some(full) = &self.full {
+            write!(f, ", full {}", full)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for PressureStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "avg10: {:.2}%, avg60: {:.2}%, avg300: {:.2}%, total: {} ms, stalled: {:.2}%",
+               self.avg10, self.avg60, self.avg300, self.total / 1000, self.stalled)
+    }
+}
+
+impl PressureStats {
+    fn parse(line: &str) -> Option<(String, PressureStats)> {
+        let mut fields = line.split_whitespace();
+        let kind = fields.next()?.to_string();
+
+        let mut stats = PressureStats::default();
+        for field in fields {
+            let (key, value) = field.split_once('=')?;
+            match key {
+                "avg10" => stats.avg10 = value.parse().ok()?,
+                "avg60" => stats.avg60 = value.parse().ok()?,
+                "avg300" => stats.avg300 = value.parse().ok()?,
+                "total" => stats.total = value.parse().ok()?,
+                _ => (),
+            }
+        }
+
+        Some((kind, stats))
+    }
+}
+
+impl ResourcePressure {
+    fn read(paths: &HostPaths, resource: &str) -> Result<Self, std::io::Error> {
+        let content = fs::read_to_string(paths.resolve(PRESSURE_PATH).join(resource))?;
+
+        let mut some = PressureStats::default();
+        let mut full = None;
+        for (kind, stats) in content.lines().filter_map(PressureStats::parse) {
+            match kind.as_str() {
+                "some" => some = stats,
+                "full" => full = Some(stats),
+                _ => (),
+            }
+        }
+
+        Ok(ResourcePressure { resource: resource.to_string(), some, full })
+    }
+}
+
+/// Registered PSI trigger, the kernel keeps it alive while the file is open.
+struct PsiTrigger {
+    config: PsiTriggerConfig,
+    file: File,
+}
+
+impl PsiTrigger {
+    fn register(paths: &HostPaths, config: &PsiTriggerConfig) -> Result<Self, std::io::Error> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(paths.resolve(PRESSURE_PATH).join(config.get_resource()))?;
+
+        file.write_all(trigger_string(config).as_bytes())?;
+
+        Ok(PsiTrigger { config: config.clone(), file })
+    }
+}
+
+/// The "<some|full> <threshold us> <window us>" line registering a trigger,
+/// the kernel replaces the last written byte with NUL.
+fn trigger_string(config: &PsiTriggerConfig) -> String {
+    format!("{} {} {}\0", config.get_kind(), config.get_threshold_us(), config.get_window_us())
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct PressureConfig {
+    triggers: Vec<PsiTriggerConfig>,
+}
+
+impl PressureConfig {
+    pub fn get_triggers(&self) -> &Vec<PsiTriggerConfig> {
+        &self.triggers
+    }
+}
+
+/// PSI trigger written to /proc/pressure/<resource>, see Documentation/accounting/psi.rst.
+/// The kernel accepts windows from 500ms to 10s, multiples of 2s without CAP_SYS_RESOURCE.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct PsiTriggerConfig {
+    resource: String,
+    kind: String,
+    threshold_us: u64,
+    window_us: u64,
+}
+
+impl Display for PsiTriggerConfig {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {} {}us in {}us", self.resource, self.kind, self.threshold_us, self.window_us)
+    }
+}
+
+impl PsiTriggerConfig {
+    /// One of `cpu`, `memory` or `io`.
+    pub fn get_resource(&self) -> &str {
+        &self.resource
+    }
+
+    /// Either `some` or `full`.
+    pub fn get_kind(&self) -> &str {
+        &self.kind
+    }
+
+    pub fn get_threshold_us(&self) -> u64 {
+        self.threshold_us
+    }
+
+    pub fn get_window_us(&self) -> u64 {
+        self.window_us
+    }
+}
+
+pub static COLLECTOR: CollectorSpec = CollectorSpec {
+    name: "psi",
+    section: "pressure_config",
+    title: "Pressure Info",
+    refresh_rate: Duration::from_secs(1),
+    default_options: || to_options(&PressureConfig::default()),
+    new: |options, paths| Ok(Box::new(PressureInfoCollector::new(&from_options(options)?, paths))),
+};
+
+pub struct PressureInfoCollector {
+    triggers_config: Vec<PsiTriggerConfig>,
+    triggers: Option<Vec<PsiTrigger>>,
+    /// Stall totals by "<resource> <kind>", e.g. "cpu some".
+    prev_totals: HashMap<String, u64>,
+    prev_time: Option<f64>,
+    paths: HostPaths,
+}
+
+impl MetricCollector for PressureInfoCollector {
+    fn collect_info(&mut self) -> QueueItem {
+        match self.get_pressure_info() {
+            Ok(pressure_info) => QueueItem::sample(&COLLECTOR, &pressure_info),
+            Err(e) => QueueItem::Err(ErrInfo::new(format!("psi: failed to read {}: {}", PRESSURE_PATH, e))),
+        }
+    }
+
+    fn reset_counters(&mut self) {
+        self.prev_totals.clear();
+        self.prev_time = None;
+    }
+
+    /// Triggers are registered on the first wait, an empty list means none
+    /// could be registered.
+    fn is_event_driven(&self) -> bool {
+        self.triggers.as_ref().is_none_or(|triggers| !triggers.is_empty())
+    }
+
+    /// Polls registered PSI triggers, so stalls are reported as soon as the
+    /// kernel signals them instead of on the next refresh.
+    fn wait(&mut self, timeout: Duration) -> Vec<QueueItem> {
+        let mut items = Vec::new();
+        if self.triggers.is_none() {
+            items = self.register_triggers();
+        }
+
+        let triggers = self.triggers.as_mut().unwrap();
+        if triggers.is_empty() {
+            sleep(timeout);
+            return items;
+        }
+
+        let mut poll_fds: Vec<libc::pollfd> = triggers
+            .iter()
+            .map(|trigger| libc::pollfd { fd: trigger.file.as_raw_fd(), events: libc::POLLPRI, revents: 0 })
+            .collect();
+
+        let timeout_ms = timeout.as_millis().min(libc::c_int::MAX as u128) as libc::c_int;
+        let res = unsafe { libc::poll(poll_fds.as_mut_ptr(), poll_fds.len() as libc::nfds_t, timeout_ms) };
+        if res < 0 {
+            let err = std::io::Error::last_os_error();
+            if err.kind() != std::io::ErrorKind::Interrupted {
+                items.push(QueueItem::Err(ErrInfo::new(format!("psi: failed to poll triggers: {}", err))));
+                self.triggers = Some(Vec::new());
+            }
+            return items;
+        }
+
+        let mut failed = Vec::new();
+        for (idx, poll_fd) in poll_fds.iter().enumerate() {
+            let config = &triggers[idx].config;
+            if poll_fd.revents & libc::POLLERR != 0 {
+                items.push(QueueItem::Err(ErrInfo::new(format!("psi: trigger '{}' is no longer available", config))));
+                failed.push(idx);
+            } else if poll_fd.revents & libc::POLLPRI != 0 {
+                let mut message = format!("stall threshold reached: {}", config);
+                if let Ok(resource_pressure) = ResourcePressure::read(&self.paths, config.get_resource()) {
+                    message = format!("{}; {}", message, resource_pressure);
+                }
+                items.push(QueueItem::Event(EventInfo::new(COLLECTOR.name, message)));
+            }
+        }
+
+        for idx in failed.into_iter().rev() {
+            triggers.remove(idx);
+        }
+
+        items
+    }
+}
+
+impl PressureInfoCollector {
+    pub fn new(config: &PressureConfig, paths: &HostPaths) -> Self {
+        Self {
+            triggers_config: config.get_triggers().clone(),
+            triggers: None,
+            prev_totals: HashMap::new(),
+            prev_time: None,
+            paths: paths.clone(),
+        }
+    }
+
+    pub fn get_pressure_info(&mut self) -> Result<PressureInfo, std::io::Error> {
+        let now = self.paths.read_uptime();
+        let elapsed = elapsed_secs(self.prev_time, now);
+        self.prev_time = now;
+
+        let mut resources_info = Vec::new();
+        let mut totals = HashMap::new();
+        for resource in PRESSURE_RESOURCES {
+            let mut resource_pressure = ResourcePressure::read(&self.paths, resource)?;
+            let ResourcePressure { some, full, .. } = &mut resource_pressure;
+            for (kind, stats) in std::iter::once(("some", some)).chain(full.as_mut().map(|full| ("full", full))) {
+                let key = format!("{} {}", resource, kind);
+                if let Some(prev) = self.prev_totals.get(&key) {
+                    if elapsed > 0.0 {
+                        // total is in microseconds
+                        stats.stalled = stats.total.saturating_sub(*prev) as f64 / (elapsed * 10_000.0);
+                    }
+                }
+                totals.insert(key, stats.total);
+            }
+            resources_info.push(resource_pressure);
+        }
+        self.prev_totals = totals;
+
+        Ok(PressureInfo { resources_info })
+    }
+
+    fn register_triggers(&mut self) -> Vec<QueueItem> {
+        let mut errors = Vec::new();
+        let mut triggers = Vec::new();
+
+        for trigger_config in &self.triggers_config {
+            match PsiTrigger::register(&self.paths, trigger_config) {
+                Ok(trigger) => triggers.push(trigger),
+                Err(e) => errors.push(QueueItem::Err(ErrInfo::new(format!("psi: failed to register trigger '{}': {}", trigger_config, e)))),
+            }
+        }
+
+        self.triggers = Some(triggers);
+        errors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metric::host::{assert_close, fixture_paths};
+    use crate::sample::find_point;
+
+    #[test]
+    fn parse_pressure_line() {
+        let (kind, stats) = PressureStats::parse("full avg10=0.03 avg60=0.05 avg300=0.01 total=4032017").unwrap();
+        assert_eq!(kind, "full");
+        assert_eq!(stats.avg10, 0.03);
+        assert_eq!(stats.avg60, 0.05);
+        assert_eq!(stats.avg300, 0.01);
+        assert_eq!(stats.total, 4032017);
+        assert!(PressureStats::parse("some avg10=x").is_none());
+    }
+
+    #[test]
+    fn pressure_between_snapshots() {
+        let mut collector = PressureInfoCollector::new(&PressureConfig::default(), &fixture_paths("kvm-guest/t0"));
+        let pressure_info = collector.get_pressure_info().unwrap();
+        let cpu = &pressure_info.resources_info[0];
+        assert_eq!(cpu.resource, "cpu");
+        assert_eq!((cpu.some.avg10, cpu.some.avg60, cpu.some.avg300, cpu.some.total), (1.49, 2.22, 2.31, 66115706));
+        assert_eq!(cpu.some.stalled, 0.0);
+
+        collector.paths = fixture_paths("kvm-guest/t1");
+        let pressure_info = collector.get_pressure_info().unwrap();
+        let resource = |name: &str| pressure_info.resources_info.iter().find(|resource| resource.resource == name).unwrap();
+
+        let cpu = resource("cpu");
+        assert_eq!((cpu.some.avg10, cpu.some.avg60, cpu.some.avg300, cpu.some.total), (5.75, 2.97, 2.46, 66527761));
+        // 1.71 s between the snapshots
+        assert_close(cpu.some.stalled, (66527761.0 - 66115706.0) / 1_710_000.0 * 100.0);
+        assert_close(cpu.full.unwrap().stalled, 0.0);
+
+        let io = resource("io");
+        assert_close(io.some.stalled, (4659168.0 - 4601480.0) / 1_710_000.0 * 100.0);
+        let io_full = io.full.unwrap();
+        assert_eq!((io_full.avg10, io_full.avg60, io_full.avg300, io_full.total), (0.20, 0.08, 0.02, 4060432));
+        assert_close(io_full.stalled, (4060432.0 - 4032017.0) / 1_710_000.0 * 100.0);
+
+        let points = pressure_info.points();
+        let stalled = find_point(&points, "pressure_stalled", &[("resource", "io"), ("kind", "full")]).unwrap();
+        assert_close(stalled.get_value(), io_full.stalled);
+    }
+
+    #[test]
+    fn trigger_string_ends_with_nul() {
+        let config: PsiTriggerConfig = toml::from_str("resource = \"memory\"\nkind = \"some\"\nthreshold_us = 150000\nwindow_us = 1000000\n").unwrap();
+        assert_eq!(trigger_string(&config), "some 150000 1000000\0");
+        assert_eq!(config.to_string(), "memory some 150000us in 1000000us");
+    }
+}
@@ -0,0 +1,427 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::{from_options, to_options};
+use crate::queue::{ErrInfo, QueueItem};
+use crate::metric::{CollectorSpec, MetricCollector};
+use crate::metric::host::{elapsed_secs, HostPaths};
+use crate::sample::{Point, SampleSource, Unit};
+
+
+const PROC_PATH: &str = "/proc";
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum ProcessSortKey {
+    Cpu,
+    Rss,
+    Read,
+    Write,
+}
+
+pub fn str_to_sort_key(sort_key: &str) -> Option<ProcessSortKey> {
+    match sort_key {
+        "cpu" => Some(ProcessSortKey::Cpu),
+        "rss" => Some(ProcessSortKey::Rss),
+        "read" => Some(ProcessSortKey::Read),
+        "write" => Some(ProcessSortKey::Write),
+        _ => None,
+    }
+}
+
+pub fn sort_key_to_str(sort_key: ProcessSortKey) -> &'static str {
+    match sort_key {
+        ProcessSortKey::Cpu => "cpu",
+        ProcessSortKey::Rss => "rss",
+        ProcessSortKey::Read => "read",
+        ProcessSortKey::Write => "write",
+    }
+}
+
+pub struct ProcessInfo {
+    sort_key: ProcessSortKey,
+    processes_count: usize,
+    processes_info: Vec<ProcessUsage>,
+}
+
+/// Resource usage of a single process between two samples.
+pub struct ProcessUsage {
+    pid: u32,
+    comm: String,
+    state: char,
+    threads: u64,
+    cpu: f32,
+    rss: u64,
+    swap: u64,
+    read_bytes_per_sec: f64,
+    write_bytes_per_sec: f64,
+}
+
+impl Display for ProcessInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Top {} of {} processes by {}:", self.processes_info.len(), self.processes_count, sort_key_to_str(self.sort_key))?;
+        for process_info in &self.processes_info {
+            writeln!(f, "{}", process_info)?;
+        }
+        Ok(())
+    }
+}
+
+impl SampleSource for ProcessInfo {
+    fn points(&self) -> Vec<Point> {
+        let mut points = vec![Point::gauge("processes", Unit::Count, self.processes_count as f64)];
+        for process in &self.processes_info {
+            let values = [
+                Point::gauge("process_cpu", Unit::Percent, process.cpu as f64),
+                Point::gauge("process_threads", Unit::Count, process.threads as f64),
+                Point::gauge("process_rss", Unit::Bytes, process.rss as f64),
+                Point::gauge("process_swap", Unit::Bytes, process.swap as f64),
+                Point::rate("process_read_bytes", Unit::Bytes, process.read_bytes_per_sec),
+                Point::rate("process_write_bytes", Unit::Bytes, process.write_bytes_per_sec),
+            ];
+            points.extend(values.into_iter().map(|point| {
+                point.label("pid", process.pid.to_string()).label("comm", &process.comm).label("state", process.state.to_string())
+            }));
+        }
+        points
+    }
+}
+
+impl Display for ProcessUsage {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, " pid: {}, comm: {}, state: {}, threads: {}, cpu: {:.2}%, rss: {} MB, swap: {} MB, read: {:.2} kB/s, write: {:.2} kB/s",
+               self.pid, self.comm, self.state, self.threads, self.cpu, self.rss / (1024 * 1024), self.swap / (1024 * 1024),
+               self.read_bytes_per_sec / 1024.0, self.write_bytes_per_sec / 1024.0)
+    }
+}
+
+/// Counters read from /proc/[pid]/{stat,statm,status,io}.
+#[derive(Clone, Debug, Default)]
+struct ProcessStats {
+    comm: String,
+    state: char,
+    cpu_ticks: u64,
+    start_time: u64,
+    rss: u64,
+    swap: u64,
+    threads: u64,
+    read_bytes: u64,
+    write_bytes: u64,
+}
+
+impl ProcessStats {
+    fn read(pid_path: &Path, page_size: u64) -> Option<Self> {
+        let stat = fs::read_to_string(pid_path.join("stat")).ok()?;
+        // comm may contain spaces and parentheses
+        let comm_start = stat.find('(')?;
+        let comm_end = stat.rfind(')')?;
+        let comm = stat[comm_start + 1..comm_end].to_string();
+        let fields: Vec<&str> = stat[comm_end + 1..].split_whitespace().collect();
+        let field = |idx: usize| fields.get(idx).and_then(|v| v.parse::<u64>().ok()).unwrap_or(0);
+
+        let mut stats = ProcessStats {
+            comm,
+            state: fields.first().and_then(|s| s.chars().next()).unwrap_or('?'),
+            cpu_ticks: field(11) + field(12),
+            start_time: field(19),
+            ..ProcessStats::default()
+        };
+
+        if let Ok(statm) = fs::read_to_string(pid_path.join("statm")) {
+            let resident = statm.split_whitespace().nth(1).and_then(|v| v.parse::<u64>().ok()).unwrap_or(0);
+            stats.rss = resident * page_size;
+        }
+
+        if let Ok(status) = fs::read_to_string(pid_path.join("status")) {
+            for line in status.lines() {
+                if let Some((key, value)) = line.split_once(':') {
+                    let value = value.split_whitespace().next().and_then(|v| v.parse::<u64>().ok()).unwrap_or(0);
+                    match key {
+                        "Threads" => stats.threads = value,
+                        "VmSwap" => stats.swap = value * 1024,
+                        _ => (),
+                    }
+                }
+            }
+        }
+
+        // io is only readable for own processes or with CAP_SYS_PTRACE
+        if let Ok(io) = fs::read_to_string(pid_path.join("io")) {
+            for line in io.lines() {
+                if let Some((key, value)) = line.split_once(':') {
+                    let value = value.trim().parse::<u64>().unwrap_or(0);
+                    match key {
+                        "read_bytes" => stats.read_bytes = value,
+                        "write_bytes" => stats.write_bytes = value,
+                        _ => (),
+                    }
+                }
+            }
+        }
+
+        Some(stats)
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(default)]
+pub struct ProcessConfig {
+    top_n: usize,
+    sort_by: String,
+    include: Vec<String>,
+    exclude: Vec<String>,
+}
+
+impl Default for ProcessConfig {
+    fn default() -> Self {
+        ProcessConfig {
+            top_n: 10,
+            sort_by: String::from("cpu"),
+            include: Vec::new(),
+            exclude: Vec::new(),
+        }
+    }
+}
+
+impl ProcessConfig {
+    pub fn get_top_n(&self) -> usize {
+        self.top_n
+    }
+
+    /// One of `cpu`, `rss`, `read` or `write`.
+    pub fn get_sort_by(&self) -> &str {
+        &self.sort_by
+    }
+
+    /// Only processes whose comm or cmdline contain one of the patterns are reported.
+    pub fn get_include(&self) -> &Vec<String> {
+        &self.include
+    }
+
+    /// Processes whose comm or cmdline contain one of the patterns are skipped.
+    pub fn get_exclude(&self) -> &Vec<String> {
+        &self.exclude
+    }
+}
+
+pub static COLLECTOR: CollectorSpec = CollectorSpec {
+    name: "process",
+    section: "process_config",
+    title: "Process Info",
+    refresh_rate: Duration::from_secs(5),
+    default_options: || to_options(&ProcessConfig::default()),
+    new: |options, paths| Ok(Box::new(ProcessInfoCollector::new(&from_options(options)?, paths)?)),
+};
+
+pub struct ProcessInfoCollector {
+    top_n: usize,
+    sort_key: ProcessSortKey,
+    include: Vec<String>,
+    exclude: Vec<String>,
+    page_size: u64,
+    clock_ticks: u64,
+    prev_stats: HashMap<u32, ProcessStats>,
+    prev_time: Option<f64>,
+    paths: HostPaths,
+}
+
+impl MetricCollector for ProcessInfoCollector {
+    fn collect_info(&mut self) -> QueueItem {
+        match self.get_process_info() {
+            Ok(process_info) => QueueItem::sample(&COLLECTOR, &process_info),
+            Err(e) => QueueItem::Err(ErrInfo::new(format!("process: failed to read {}: {}", PROC_PATH, e))),
+        }
+    }
+
+    fn reset_counters(&mut self) {
+        self.prev_stats.clear();
+        self.prev_time = None;
+    }
+}
+
+impl ProcessInfoCollector {
+    pub fn new(config: &ProcessConfig, paths: &HostPaths) -> Result<Self, String> {
+        let sort_key = str_to_sort_key(config.get_sort_by())
+            .ok_or_else(|| format!("invalid sort_by \"{}\", expected cpu, rss, read or write", config.get_sort_by()))?;
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+        let clock_ticks = unsafe { libc::sysconf(libc::_SC_CLK_TCK) };
+
+        Ok(Self {
+            top_n: config.get_top_n(),
+            sort_key,
+            include: config.get_include().clone(),
+            exclude: config.get_exclude().clone(),
+            page_size: if page_size > 0 { page_size as u64 } else { 4096 },
+            clock_ticks: if clock_ticks > 0 { clock_ticks as u64 } else { 100 },
+            prev_stats: HashMap::new(),
+            prev_time: None,
+            paths: paths.clone(),
+        })
+    }
+
+    /// Walks /proc and returns the top processes by the configured key.
+    /// Rates of processes seen for the first time are reported as zero.
+    pub fn get_process_info(&mut self) -> Result<ProcessInfo, std::io::Error> {
+        let now = self.paths.read_uptime();
+        let elapsed_secs = elapsed_secs(self.prev_time, now);
+
+        let mut stats_map = HashMap::new();
+        let mut processes_info = Vec::new();
+
+        for entry in fs::read_dir(self.paths.resolve(PROC_PATH))? {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(_) => continue,
+            };
+            let pid = match entry.file_name().to_str().and_then(|name| name.parse::<u32>().ok()) {
+                Some(pid) => pid,
+                None => continue,
+            };
+
+            // the process may exit while being read
+            let stats = match ProcessStats::read(&entry.path(), self.page_size) {
+                Some(stats) => stats,
+                None => continue,
+            };
+
+            if !self.is_process_included(&entry.path(), &stats.comm) {
+                continue;
+            }
+
+            let prev = self.prev_stats.get(&pid).filter(|prev| prev.start_time == stats.start_time);
+            processes_info.push(self.get_process_usage(pid, prev, &stats, elapsed_secs));
+            stats_map.insert(pid, stats);
+        }
+
+        let processes_count = processes_info.len();
+        match self.sort_key {
+            ProcessSortKey::Cpu => processes_info.sort_by(|a, b| b.cpu.total_cmp(&a.cpu)),
+            ProcessSortKey::Rss => processes_info.sort_by_key(|p| std::cmp::Reverse(p.rss)),
+            ProcessSortKey::Read => processes_info.sort_by(|a, b| b.read_bytes_per_sec.total_cmp(&a.read_bytes_per_sec)),
+            ProcessSortKey::Write => processes_info.sort_by(|a, b| b.write_bytes_per_sec.total_cmp(&a.write_bytes_per_sec)),
+        }
+        processes_info.truncate(self.top_n);
+
+        self.prev_stats = stats_map;
+        self.prev_time = now;
+
+        Ok(ProcessInfo { sort_key: self.sort_key, processes_count, processes_info })
+    }
+
+    fn get_process_usage(&self, pid: u32, prev: Option<&ProcessStats>, curr: &ProcessStats, elapsed_secs: f64) -> ProcessUsage {
+        let mut usage = ProcessUsage {
+            pid,
+            comm: curr.comm.clone(),
+            state: curr.state,
+            threads: curr.threads,
+            cpu: 0.0,
+            rss: curr.rss,
+            swap: curr.swap,
+            read_bytes_per_sec: 0.0,
+            write_bytes_per_sec: 0.0,
+        };
+
+        if let Some(prev) = prev {
+            if elapsed_secs > 0.0 {
+                let cpu_secs = curr.cpu_ticks.saturating_sub(prev.cpu_ticks) as f64 / self.clock_ticks as f64;
+                usage.cpu = (cpu_secs * 100.0 / elapsed_secs) as f32;
+                usage.read_bytes_per_sec = curr.read_bytes.saturating_sub(prev.read_bytes) as f64 / elapsed_secs;
+                usage.write_bytes_per_sec = curr.write_bytes.saturating_sub(prev.write_bytes) as f64 / elapsed_secs;
+            }
+        }
+
+        usage
+    }
+
+    /// Patterns are matched as substrings of the process comm or cmdline.
+    fn is_process_included(&self, pid_path: &Path, comm: &str) -> bool {
+        if self.include.is_empty() && self.exclude.is_empty() {
+            return true;
+        }
+
+        let cmdline = fs::read(pid_path.join("cmdline"))
+            .map(|cmdline| String::from_utf8_lossy(&cmdline).replace('\0', " "))
+            .unwrap_or_default();
+        let matches = |pattern: &String| comm.contains(pattern.as_str()) || cmdline.contains(pattern.as_str());
+
+        if self.exclude.iter().any(matches) {
+            return false;
+        }
+
+        self.include.is_empty() || self.include.iter().any(matches)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metric::host::{assert_close, fixture_paths};
+
+    fn collector(top_n: usize, sort_by: &str) -> ProcessInfoCollector {
+        let config = ProcessConfig { top_n, sort_by: sort_by.to_string(), ..ProcessConfig::default() };
+        ProcessInfoCollector::new(&config, &fixture_paths("scenarios/bare-metal/t0")).unwrap()
+    }
+
+    fn collect_t1(collector: &mut ProcessInfoCollector) -> ProcessInfo {
+        collector.get_process_info().unwrap();
+        collector.paths = fixture_paths("scenarios/bare-metal/t1");
+        collector.get_process_info().unwrap()
+    }
+
+    fn pids(process_info: &ProcessInfo) -> Vec<u32> {
+        process_info.processes_info.iter().map(|process| process.pid).collect()
+    }
+
+    #[test]
+    fn top_processes_by_cpu() {
+        let mut collector = collector(3, "cpu");
+        let ticks = collector.clock_ticks as f64;
+        let process_info = collect_t1(&mut collector);
+        assert_eq!(process_info.processes_count, 5);
+        assert_eq!(pids(&process_info), [812, 1377, 1]);
+
+        // 2.5 s between the snapshots
+        let postgres = &process_info.processes_info[0];
+        assert_eq!((postgres.comm.as_str(), postgres.state, postgres.threads), ("postgres", 'R', 1));
+        assert_close(postgres.cpu as f64, 130.0 / ticks * 100.0 / 2.5);
+        assert_eq!(postgres.rss, 14210 * collector.page_size);
+        assert_eq!(postgres.swap, 512 * 1024);
+        assert_close(postgres.write_bytes_per_sec, 2097152.0 / 2.5);
+        assert_close(process_info.processes_info[1].cpu as f64, 70.0 / ticks * 100.0 / 2.5);
+        assert_close(process_info.processes_info[2].cpu as f64, 1.0 / ticks * 100.0 / 2.5);
+    }
+
+    #[test]
+    fn top_processes_by_io() {
+        let process_info = collect_t1(&mut collector(1, "read"));
+        assert_eq!(pids(&process_info), [1377]);
+        let rsync = &process_info.processes_info[0];
+        assert_close(rsync.read_bytes_per_sec, 52428800.0 / 2.5);
+        assert_close(rsync.write_bytes_per_sec, 1048576.0 / 2.5);
+
+        let process_info = collect_t1(&mut collector(1, "write"));
+        assert_eq!(pids(&process_info), [812]);
+    }
+
+    #[test]
+    fn comm_with_spaces_and_new_processes() {
+        let process_info = collect_t1(&mut collector(10, "rss"));
+        assert_eq!(pids(&process_info), [812, 1, 1377, 2210, 2301]);
+        let tmux = &process_info.processes_info[3];
+        assert_eq!(tmux.comm, "tmux: server");
+        // io is not readable
+        assert_eq!(tmux.read_bytes_per_sec, 0.0);
+        // seen for the first time
+        assert_eq!(process_info.processes_info[4].cpu, 0.0);
+    }
+
+    #[test]
+    fn invalid_sort_by() {
+        let config = ProcessConfig { sort_by: String::from("memory"), ..ProcessConfig::default() };
+        assert!(ProcessInfoCollector::new(&config, &fixture_paths("scenarios/bare-metal/t0")).is_err());
+    }
+}
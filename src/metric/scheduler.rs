@@ -0,0 +1,236 @@
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::time::Duration;
+
+use crate::queue::{ErrInfo, QueueItem};
+use crate::metric::{CollectorSpec, MetricCollector};
+use crate::metric::host::{elapsed_secs, HostPaths};
+use crate::sample::{Point, SampleSource, Unit};
+
+
+const LOADAVG_PATH: &str = "/proc/loadavg";
+const PROC_STAT_PATH: &str = "/proc/stat";
+
+/// System load from /proc/loadavg and scheduler counters from /proc/stat.
+pub struct SchedulerInfo {
+    load1: f32,
+    load5: f32,
+    load15: f32,
+    online_cpus: u64,
+    runnable_tasks: u64,
+    total_tasks: u64,
+    last_pid: u64,
+    procs_running: u64,
+    procs_blocked: u64,
+    context_switches: f64,
+    forks: f64,
+    interrupts: f64,
+}
+
+impl Display for SchedulerInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let cpus = self.online_cpus.max(1) as f32;
+        writeln!(f, "Load average: {:.2} {:.2} {:.2}, normalised: {:.2} {:.2} {:.2} ({} online cpus)",
+                 self.load1, self.load5, self.load15, self.load1 / cpus, self.load5 / cpus, self.load15 / cpus, self.online_cpus)?;
+        writeln!(f, "Tasks: {} runnable of {}, last pid: {}, procs running: {}, procs blocked: {}",
+                 self.runnable_tasks, self.total_tasks, self.last_pid, self.procs_running, self.procs_blocked)?;
+        writeln!(f, "Context switches: {:.2}/s, forks: {:.2}/s, interrupts: {:.2}/s", self.context_switches, self.forks, self.interrupts)?;
+        Ok(())
+    }
+}
+
+impl SampleSource for SchedulerInfo {
+    fn points(&self) -> Vec<Point> {
+        let cpus = self.online_cpus.max(1) as f64;
+        vec![
+            Point::gauge("load1", Unit::Count, self.load1 as f64),
+            Point::gauge("load5", Unit::Count, self.load5 as f64),
+            Point::gauge("load15", Unit::Count, self.load15 as f64),
+            Point::gauge("load1_per_cpu", Unit::Ratio, self.load1 as f64 / cpus),
+            Point::gauge("load5_per_cpu", Unit::Ratio, self.load5 as f64 / cpus),
+            Point::gauge("load15_per_cpu", Unit::Ratio, self.load15 as f64 / cpus),
+            Point::gauge("online_cpus", Unit::Count, self.online_cpus as f64),
+            Point::gauge("tasks_runnable", Unit::Count, self.runnable_tasks as f64),
+            Point::gauge("tasks", Unit::Count, self.total_tasks as f64),
+            Point::gauge("last_pid", Unit::Count, self.last_pid as f64),
+            Point::gauge("procs_running", Unit::Count, self.procs_running as f64),
+            Point::gauge("procs_blocked", Unit::Count, self.procs_blocked as f64),
+            Point::rate("context_switches", Unit::Count, self.context_switches),
+            Point::rate("forks", Unit::Count, self.forks),
+            Point::rate("interrupts", Unit::Count, self.interrupts),
+        ]
+    }
+}
+
+/// Cumulative /proc/stat counters used for rates, and the number of online
+/// cpus, which /proc/stat has one cpuN line for.
+#[derive(Clone, Copy, Default, Debug)]
+struct SchedulerStats {
+    cpus: u64,
+    ctxt: u64,
+    processes: u64,
+    intr: u64,
+    procs_running: u64,
+    procs_blocked: u64,
+}
+
+impl SchedulerStats {
+    fn parse(stat: &str) -> Self {
+        let mut stats = SchedulerStats::default();
+        for line in stat.lines() {
+            let mut fields = line.split_whitespace();
+            let key = fields.next().unwrap_or_default();
+            // intr is followed by per-irq counters, the first value is the total
+            let value = fields.next().and_then(|v| v.parse::<u64>().ok()).unwrap_or(0);
+            match key {
+                cpu if cpu.strip_prefix("cpu").is_some_and(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit())) => stats.cpus += 1,
+                "ctxt" => stats.ctxt = value,
+                "processes" => stats.processes = value,
+                "intr" => stats.intr = value,
+                "procs_running" => stats.procs_running = value,
+                "procs_blocked" => stats.procs_blocked = value,
+                _ => (),
+            }
+        }
+        stats
+    }
+}
+
+pub static COLLECTOR: CollectorSpec = CollectorSpec {
+    name: "sched",
+    section: "scheduler_config",
+    title: "Scheduler Info",
+    refresh_rate: Duration::from_secs(1),
+    default_options: toml::Table::new,
+    new: |_, paths| Ok(Box::new(SchedulerInfoCollector::new(paths))),
+};
+
+pub struct SchedulerInfoCollector {
+    prev_stats: Option<SchedulerStats>,
+    prev_time: Option<f64>,
+    paths: HostPaths,
+}
+
+impl MetricCollector for SchedulerInfoCollector {
+    fn collect_info(&mut self) -> QueueItem {
+        match self.get_scheduler_info() {
+            Ok(scheduler_info) => QueueItem::sample(&COLLECTOR, &scheduler_info),
+            Err(e) => QueueItem::Err(ErrInfo::new(format!("sched: failed to read scheduler stats: {}", e))),
+        }
+    }
+
+    fn reset_counters(&mut self) {
+        self.prev_stats = None;
+        self.prev_time = None;
+    }
+}
+
+impl SchedulerInfoCollector {
+    pub fn new(paths: &HostPaths) -> Self {
+        Self {
+            prev_stats: None,
+            prev_time: None,
+            paths: paths.clone(),
+        }
+    }
+
+    /// Rates are computed since the previous call and reported as zero on the first one.
+    pub fn get_scheduler_info(&mut self) -> Result<SchedulerInfo, std::io::Error> {
+        let loadavg = fs::read_to_string(self.paths.resolve(LOADAVG_PATH))?;
+        let stat = fs::read_to_string(self.paths.resolve(PROC_STAT_PATH))?;
+        let now = self.paths.read_uptime();
+
+        // "0.20 0.18 0.12 1/80 11206"
+        let fields: Vec<&str> = loadavg.split_whitespace().collect();
+        let load = |idx: usize| fields.get(idx).and_then(|v| v.parse::<f32>().ok()).unwrap_or(0.0);
+        let (runnable_tasks, total_tasks) = fields
+            .get(3)
+            .and_then(|tasks| tasks.split_once('/'))
+            .map(|(runnable, total)| (runnable.parse::<u64>().unwrap_or(0), total.parse::<u64>().unwrap_or(0)))
+            .unwrap_or((0, 0));
+        let last_pid = fields.get(4).and_then(|v| v.parse::<u64>().ok()).unwrap_or(0);
+
+        let stats = SchedulerStats::parse(&stat);
+        let prev = self.prev_stats.unwrap_or(stats);
+        let elapsed_secs = elapsed_secs(self.prev_time, now);
+        let rate = |prev: u64, curr: u64| {
+            if elapsed_secs > 0.0 {
+                curr.saturating_sub(prev) as f64 / elapsed_secs
+            } else {
+                0.0
+            }
+        };
+
+        let scheduler_info = SchedulerInfo {
+            load1: load(0),
+            load5: load(1),
+            load15: load(2),
+            online_cpus: stats.cpus.max(1),
+            runnable_tasks,
+            total_tasks,
+            last_pid,
+            procs_running: stats.procs_running,
+            procs_blocked: stats.procs_blocked,
+            context_switches: rate(prev.ctxt, stats.ctxt),
+            forks: rate(prev.processes, stats.processes),
+            interrupts: rate(prev.intr, stats.intr),
+        };
+
+        self.prev_stats = Some(stats);
+        self.prev_time = now;
+
+        Ok(scheduler_info)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metric::host::{assert_close, fixture_paths};
+    use crate::sample::find_point;
+
+    #[test]
+    fn scheduler_rates_between_snapshots() {
+        let mut collector = SchedulerInfoCollector::new(&fixture_paths("kvm-guest/t0"));
+        let scheduler_info = collector.get_scheduler_info().unwrap();
+        assert_eq!(scheduler_info.total_tasks, 72);
+        assert_eq!(scheduler_info.runnable_tasks, 2);
+        assert_eq!(scheduler_info.last_pid, 16685);
+        assert_close(scheduler_info.context_switches, 0.0);
+
+        collector.paths = fixture_paths("kvm-guest/t1");
+        let scheduler_info = collector.get_scheduler_info().unwrap();
+        assert_close(scheduler_info.load1 as f64, 0.26);
+        assert_eq!(scheduler_info.runnable_tasks, 1);
+        assert_eq!(scheduler_info.last_pid, 16935);
+        assert_eq!(scheduler_info.procs_running, 3);
+        assert_eq!(scheduler_info.procs_blocked, 0);
+        assert_close(scheduler_info.context_switches, 13682.0 / 1.71);
+        assert_close(scheduler_info.forks, 247.0 / 1.71);
+        assert_close(scheduler_info.interrupts, 385.0 / 1.71);
+        assert_eq!(scheduler_info.online_cpus, 1);
+    }
+
+    #[test]
+    fn online_cpus_from_proc_stat() {
+        let stat = "cpu  400 0 100 5000 0 0 0 0 0 0\n\
+                    cpu0 100 0 25 1250 0 0 0 0 0 0\n\
+                    cpu1 100 0 25 1250 0 0 0 0 0 0\n\
+                    cpu2 100 0 25 1250 0 0 0 0 0 0\n\
+                    cpu3 100 0 25 1250 0 0 0 0 0 0\n\
+                    ctxt 1000\n";
+        assert_eq!(SchedulerStats::parse(stat).cpus, 4);
+    }
+
+    #[test]
+    fn load_per_cpu_points() {
+        let mut collector = SchedulerInfoCollector::new(&fixture_paths("kvm-guest/t1"));
+        let mut scheduler_info = collector.get_scheduler_info().unwrap();
+        scheduler_info.online_cpus = 4;
+        let points = scheduler_info.points();
+        assert_close(find_point(&points, "load1", &[]).unwrap().get_value(), 0.26);
+        assert_close(find_point(&points, "load1_per_cpu", &[]).unwrap().get_value(), 0.26 / 4.0);
+        assert_close(find_point(&points, "load5_per_cpu", &[]).unwrap().get_value(), 0.25 / 4.0);
+        assert_close(find_point(&points, "load15_per_cpu", &[]).unwrap().get_value(), 0.19 / 4.0);
+    }
+}
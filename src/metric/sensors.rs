@@ -0,0 +1,316 @@
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::queue::QueueItem;
+use crate::metric::{CollectorSpec, MetricCollector};
+use crate::metric::host::HostPaths;
+use crate::sample::{Point, SampleSource, Unit};
+
+
+const THERMAL_PATH: &str = "/sys/class/thermal";
+const HWMON_PATH: &str = "/sys/class/hwmon";
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum SensorKind {
+    Temperature,
+    Fan,
+    Voltage,
+}
+
+pub struct SensorsInfo {
+    sensors_info: Vec<SensorInfo>,
+}
+
+/// A single reading, temperatures in °C, fans in RPM and voltages in V.
+/// `passive` and `hot` are the temperatures the kernel starts throttling and
+/// notifies at, `critical` the one it shuts down at.
+pub struct SensorInfo {
+    chip: String,
+    label: String,
+    kind: SensorKind,
+    value: f64,
+    passive: Option<f64>,
+    hot: Option<f64>,
+    critical: Option<f64>,
+}
+
+impl SensorInfo {
+    pub fn is_critical(&self) -> bool {
+        self.critical.is_some_and(|critical| self.value >= critical)
+    }
+}
+
+impl Display for SensorsInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.sensors_info.is_empty() {
+            return writeln!(f, "No thermal zones or hwmon sensors");
+        }
+        for sensor_info in &self.sensors_info {
+            writeln!(f, "{}", sensor_info)?;
+        }
+        Ok(())
+    }
+}
+
+impl SampleSource for SensorsInfo {
+    fn points(&self) -> Vec<Point> {
+        let mut points = Vec::new();
+        for sensor in &self.sensors_info {
+            let (name, unit) = match sensor.kind {
+                SensorKind::Temperature => ("temperature", Unit::Celsius),
+                SensorKind::Fan => ("fan_speed", Unit::Rpm),
+                SensorKind::Voltage => ("voltage", Unit::Volts),
+            };
+            let mut values = vec![Point::gauge(name, unit, sensor.value)];
+            let trips = [("passive", sensor.passive), ("hot", sensor.hot), ("critical", sensor.critical)];
+            for (trip, temp) in trips {
+                if let Some(temp) = temp {
+                    values.push(Point::gauge(format!("{}_{}", name, trip), unit, temp));
+                }
+            }
+            if sensor.critical.is_some() {
+                values.push(Point::gauge("sensor_critical", Unit::Count, if sensor.is_critical() { 1.0 } else { 0.0 }));
+            }
+            points.extend(values.into_iter().map(|point| point.label("chip", &sensor.chip).label("sensor", &sensor.label)));
+        }
+        points
+    }
+}
+
+impl Display for SensorInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.kind {
+            SensorKind::Temperature => write!(f, "{} {}: {:.1} °C", self.chip, self.label, self.value)?,
+            SensorKind::Fan => write!(f, "{} {}: {:.0} RPM", self.chip, self.label, self.value)?,
+            SensorKind::Voltage => write!(f, "{} {}: {:.3} V", self.chip, self.label, self.value)?,
+        }
+        let trips = [("passive", self.passive), ("hot", self.hot), ("critical", self.critical)];
+        for (trip, temp) in trips {
+            if let Some(temp) = temp {
+                write!(f, ", {}: {:.1} °C", trip, temp)?;
+            }
+        }
+        if self.is_critical() {
+            write!(f, " [CRITICAL]")?;
+        }
+        Ok(())
+    }
+}
+
+fn read_string(path: &Path) -> Option<String> {
+    fs::read_to_string(path).ok().map(|s| s.trim().to_string())
+}
+
+fn read_number(path: &Path) -> Option<f64> {
+    read_string(path)?.parse::<f64>().ok()
+}
+
+/// Entries of a sysfs class directory matching `prefix`, sorted by name.
+fn class_entries(path: &Path, prefix: &str) -> Vec<PathBuf> {
+    let mut entries: Vec<PathBuf> = match fs::read_dir(path) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_name().to_string_lossy().starts_with(prefix))
+            .map(|entry| entry.path())
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    entries.sort();
+    entries
+}
+
+pub static COLLECTOR: CollectorSpec = CollectorSpec {
+    name: "sensors",
+    section: "sensors_config",
+    title: "Sensors Info",
+    refresh_rate: Duration::from_secs(5),
+    default_options: toml::Table::new,
+    new: |_, paths| Ok(Box::new(SensorsInfoCollector::new(paths))),
+};
+
+pub struct SensorsInfoCollector {
+    paths: HostPaths,
+}
+
+impl MetricCollector for SensorsInfoCollector {
+    /// Hosts without sensors, e.g. most VMs, report an empty sample.
+    fn collect_info(&mut self) -> QueueItem {
+        QueueItem::sample(&COLLECTOR, &self.get_sensors_info())
+    }
+}
+
+impl SensorsInfoCollector {
+    pub fn new(paths: &HostPaths) -> Self {
+        Self {
+            paths: paths.clone(),
+        }
+    }
+
+    pub fn get_sensors_info(&mut self) -> SensorsInfo {
+        let mut sensors_info = self.get_thermal_zones();
+        sensors_info.extend(self.get_hwmon_sensors());
+
+        SensorsInfo { sensors_info }
+    }
+
+    /// Reads class/thermal/thermal_zone*, the passive, hot and critical temperatures
+    /// are taken from the trip points of these types.
+    fn get_thermal_zones(&self) -> Vec<SensorInfo> {
+        let mut sensors_info = Vec::new();
+
+        for zone_path in class_entries(&self.paths.resolve(THERMAL_PATH), "thermal_zone") {
+            let temp = match read_number(&zone_path.join("temp")) {
+                Some(temp) => temp / 1000.0,
+                None => continue,
+            };
+
+            let (mut passive, mut hot, mut critical) = (None, None, None);
+            for trip in 0.. {
+                let trip_type = match read_string(&zone_path.join(format!("trip_point_{}_type", trip))) {
+                    Some(trip_type) => trip_type,
+                    None => break,
+                };
+                let trip_temp = read_number(&zone_path.join(format!("trip_point_{}_temp", trip))).map(|t| t / 1000.0);
+                // zones can have several passive trip points, the lowest one starts throttling
+                match trip_type.as_str() {
+                    "passive" => passive = passive.into_iter().chain(trip_temp).reduce(f64::min),
+                    "hot" => hot = trip_temp,
+                    "critical" => critical = trip_temp,
+                    _ => {}
+                }
+            }
+
+            sensors_info.push(SensorInfo {
+                chip: zone_path.file_name().unwrap_or_default().to_string_lossy().to_string(),
+                label: read_string(&zone_path.join("type")).unwrap_or_default(),
+                kind: SensorKind::Temperature,
+                value: temp,
+                passive,
+                hot,
+                critical,
+            });
+        }
+
+        sensors_info
+    }
+
+    /// Reads class/hwmon/hwmon*/{temp,fan,in}*_input with their labels.
+    fn get_hwmon_sensors(&self) -> Vec<SensorInfo> {
+        let mut sensors_info = Vec::new();
+
+        for hwmon_path in class_entries(&self.paths.resolve(HWMON_PATH), "hwmon") {
+            let chip = read_string(&hwmon_path.join("name"))
+                .unwrap_or_else(|| hwmon_path.file_name().unwrap_or_default().to_string_lossy().to_string());
+
+            let inputs: Vec<String> = class_entries(&hwmon_path, "")
+                .iter()
+                .filter_map(|path| path.file_name().map(|name| name.to_string_lossy().to_string()))
+                .filter(|name| name.ends_with("_input"))
+                .collect();
+
+            for input in inputs {
+                let channel = input.trim_end_matches("_input");
+                let (kind, scale) = if channel.starts_with("temp") {
+                    (SensorKind::Temperature, 1000.0)
+                } else if channel.starts_with("fan") {
+                    (SensorKind::Fan, 1.0)
+                } else if channel.starts_with("in") {
+                    (SensorKind::Voltage, 1000.0)
+                } else {
+                    continue;
+                };
+
+                let value = match read_number(&hwmon_path.join(&input)) {
+                    Some(value) => value / scale,
+                    None => continue,
+                };
+                // hwmon has no passive trip, `_max` is the temperature its alarm goes off at
+                let read_temp = |suffix: &str| match kind {
+                    SensorKind::Temperature => read_number(&hwmon_path.join(format!("{}_{}", channel, suffix))).map(|t| t / 1000.0),
+                    _ => None,
+                };
+
+                sensors_info.push(SensorInfo {
+                    chip: chip.clone(),
+                    label: read_string(&hwmon_path.join(format!("{}_label", channel))).unwrap_or_else(|| channel.to_string()),
+                    kind,
+                    value,
+                    passive: None,
+                    hot: read_temp("max"),
+                    critical: read_temp("crit"),
+                });
+            }
+        }
+
+        sensors_info
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metric::host::fixture_paths;
+    use crate::sample::find_point;
+
+    #[test]
+    fn thermal_zones_and_hwmon() {
+        let mut collector = SensorsInfoCollector::new(&fixture_paths("scenarios/bare-metal/t0"));
+        let sensors_info = collector.get_sensors_info();
+        let readings: Vec<(&str, &str, f64)> = sensors_info.sensors_info
+            .iter()
+            .map(|sensor| (sensor.chip.as_str(), sensor.label.as_str(), sensor.value))
+            .collect();
+        assert_eq!(readings, [
+            ("thermal_zone0", "acpitz", 27.8),
+            ("thermal_zone1", "x86_pkg_temp", 46.0),
+            ("coretemp", "Package id 0", 46.0),
+            ("coretemp", "Core 0", 44.0),
+            ("nct6775", "fan1", 1021.0),
+            ("nct6775", "Vcore", 0.88),
+        ]);
+
+        let acpitz = &sensors_info.sensors_info[0];
+        // the lowest of the two passive trip points
+        assert_eq!((acpitz.passive, acpitz.hot, acpitz.critical), (Some(90.0), Some(110.0), Some(119.0)));
+        assert!(!acpitz.is_critical());
+        let package = &sensors_info.sensors_info[2];
+        assert_eq!((package.passive, package.hot, package.critical), (None, Some(84.0), Some(100.0)));
+        assert_eq!(sensors_info.sensors_info[4].kind, SensorKind::Fan);
+        assert_eq!(sensors_info.sensors_info[4].critical, None);
+
+        let points = sensors_info.points();
+        let passive = find_point(&points, "temperature_passive", &[("chip", "thermal_zone0")]).unwrap();
+        assert_eq!(passive.get_value(), 90.0);
+        let voltage = find_point(&points, "voltage", &[("sensor", "Vcore")]).unwrap();
+        assert_eq!(voltage.get_unit(), Unit::Volts);
+        assert_eq!(find_point(&points, "sensor_critical", &[("chip", "thermal_zone0")]).unwrap().get_value(), 0.0);
+        assert!(find_point(&points, "sensor_critical", &[("chip", "nct6775")]).is_none());
+    }
+
+    #[test]
+    fn critical_temperature_point() {
+        let sensor = SensorInfo {
+            chip: String::from("coretemp"),
+            label: String::from("Package id 0"),
+            kind: SensorKind::Temperature,
+            value: 100.0,
+            passive: None,
+            hot: Some(84.0),
+            critical: Some(100.0),
+        };
+        assert!(sensor.to_string().ends_with("[CRITICAL]"));
+        let points = SensorsInfo { sensors_info: vec![sensor] }.points();
+        assert_eq!(find_point(&points, "sensor_critical", &[("sensor", "Package id 0")]).unwrap().get_value(), 1.0);
+    }
+
+    #[test]
+    fn no_sensors_is_an_empty_sample() {
+        let mut collector = SensorsInfoCollector::new(&fixture_paths("kvm-guest/t0"));
+        let sensors_info = collector.get_sensors_info();
+        assert!(sensors_info.sensors_info.is_empty());
+        assert_eq!(sensors_info.to_string(), "No thermal zones or hwmon sensors\n");
+        assert!(matches!(collector.collect_info(), QueueItem::Sample(_)));
+    }
+}
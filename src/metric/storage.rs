@@ -0,0 +1,589 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::os::fd::AsRawFd;
+use std::path::Path;
+use std::thread::sleep;
+use std::time::Duration;
+
+use crate::queue::{ErrInfo, EventInfo, QueueItem};
+use crate::metric::{CollectorSpec, MetricCollector};
+use crate::metric::host::HostPaths;
+use crate::sample::{Point, SampleSource, Unit};
+
+
+const MDSTAT_PATH: &str = "/proc/mdstat";
+const SYS_BLOCK_PATH: &str = "/sys/block";
+
+pub struct StorageInfo {
+    arrays_info: Vec<MdArrayInfo>,
+    dm_devices_info: Vec<DmDeviceInfo>,
+}
+
+impl Display for StorageInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.arrays_info.is_empty() && self.dm_devices_info.is_empty() {
+            return writeln!(f, "No md arrays or dm devices");
+        }
+        for array_info in &self.arrays_info {
+            writeln!(f, "{}", array_info)?;
+        }
+        for dm_device_info in &self.dm_devices_info {
+            writeln!(f, "{}", dm_device_info)?;
+        }
+        Ok(())
+    }
+}
+
+impl SampleSource for StorageInfo {
+    fn points(&self) -> Vec<Point> {
+        let mut points = Vec::new();
+        for array_info in &self.arrays_info {
+            let mut values = vec![
+                Point::gauge("md_disks", Unit::Count, array_info.raid_disks as f64),
+                Point::gauge("md_disks_active", Unit::Count, array_info.active_disks as f64),
+                Point::gauge("md_disks_failed", Unit::Count, array_info.failed_members.len() as f64),
+                Point::gauge("md_disks_spare", Unit::Count, array_info.spare_members.len() as f64),
+                Point::gauge("md_degraded", Unit::Count, if array_info.is_degraded() { 1.0 } else { 0.0 }),
+                Point::gauge("md_active", Unit::Count, if array_info.state.starts_with("active") { 1.0 } else { 0.0 }),
+                Point::gauge("md_read_only", Unit::Count, if array_info.state.contains("read-only") { 1.0 } else { 0.0 }),
+            ];
+            // the running action is reported in the started and finished events
+            if let Some(sync) = &array_info.sync {
+                values.push(Point::gauge("md_sync_progress", Unit::Percent, sync.progress.unwrap_or(0.0)));
+            }
+            points.extend(values.into_iter().map(|point| point.label("array", &array_info.name).label("level", &array_info.level)));
+        }
+        for dm_device_info in &self.dm_devices_info {
+            points.push(Point::gauge("dm_suspended", Unit::Count, if dm_device_info.suspended { 1.0 } else { 0.0 })
+                .label("device", &dm_device_info.device)
+                .label("name", &dm_device_info.name)
+                .label("uuid", &dm_device_info.uuid));
+        }
+        points
+    }
+}
+
+/// Resync, recovery, check, repair or reshape running on an array.
+#[derive(Clone, Debug, Default)]
+pub struct MdSync {
+    action: String,
+    /// Percent done, None while the action is delayed or pending.
+    progress: Option<f64>,
+    finish: Option<String>,
+    speed: Option<String>,
+}
+
+impl Display for MdSync {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.progress {
+            Some(progress) => write!(f, "{}: {:.1}%", self.action, progress)?,
+            None => write!(f, "{}: pending", self.action)?,
+        }
+        if let Some(finish) = &self.finish {
+            write!(f, ", finish: {}", finish)?;
+        }
+        if let Some(speed) = &self.speed {
+            write!(f, ", speed: {}", speed)?;
+        }
+        Ok(())
+    }
+}
+
+/// A single array from /proc/mdstat.
+#[derive(Clone, Debug, Default)]
+pub struct MdArrayInfo {
+    name: String,
+    state: String,
+    level: String,
+    members: Vec<String>,
+    failed_members: Vec<String>,
+    spare_members: Vec<String>,
+    raid_disks: u32,
+    active_disks: u32,
+    sync: Option<MdSync>,
+}
+
+impl MdArrayInfo {
+    /// Parses the lines of a single array, the first one being "md0 : active raid1 sdb1[1] sda1[0]".
+    fn parse(lines: &[&str]) -> Option<MdArrayInfo> {
+        let (name, header) = lines.first()?.split_once(" : ")?;
+        let mut fields = header.split_whitespace().peekable();
+
+        let mut array_info = MdArrayInfo {
+            name: name.trim().to_string(),
+            state: fields.next()?.to_string(),
+            ..Default::default()
+        };
+        // e.g. "(auto-read-only)"
+        while let Some(field) = fields.next_if(|field| field.starts_with('(')) {
+            array_info.state = format!("{} {}", array_info.state, field);
+        }
+        if array_info.state != "inactive" {
+            array_info.level = fields.next().unwrap_or_default().to_string();
+        }
+
+        for member in fields {
+            let device = member.split('[').next().unwrap_or(member).to_string();
+            if member.ends_with("(F)") {
+                array_info.failed_members.push(device);
+            } else if member.ends_with("(S)") {
+                array_info.spare_members.push(device);
+            } else {
+                array_info.members.push(device);
+            }
+        }
+
+        for line in &lines[1..] {
+            let line = line.trim();
+            if let Some((raid_disks, active_disks)) = parse_disks_status(line) {
+                array_info.raid_disks = raid_disks;
+                array_info.active_disks = active_disks;
+            } else if let Some(sync) = parse_sync(line) {
+                array_info.sync = Some(sync);
+            }
+        }
+
+        Some(array_info)
+    }
+
+    /// Arrays with missing or failed members. Inactive arrays do not report their disks.
+    pub fn is_degraded(&self) -> bool {
+        self.active_disks < self.raid_disks || !self.failed_members.is_empty()
+    }
+}
+
+impl Display for MdArrayInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, " {}: state: {}", self.name, self.state)?;
+        if !self.level.is_empty() {
+            write!(f, ", level: {}", self.level)?;
+        }
+        if self.raid_disks > 0 {
+            write!(f, ", disks: {}/{}", self.active_disks, self.raid_disks)?;
+        }
+        if !self.members.is_empty() {
+            write!(f, ", members: {}", self.members.join(" "))?;
+        }
+        if !self.failed_members.is_empty() {
+            write!(f, ", failed: {}", self.failed_members.join(" "))?;
+        }
+        if !self.spare_members.is_empty() {
+            write!(f, ", spare: {}", self.spare_members.join(" "))?;
+        }
+        if let Some(sync) = &self.sync {
+            write!(f, ", {}", sync)?;
+        }
+        if self.is_degraded() {
+            write!(f, " [DEGRADED]")?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses "[3/2]" out of "2093056 blocks super 1.2 level 5, 512k chunk, algorithm 2 [3/2] [UU_]".
+fn parse_disks_status(line: &str) -> Option<(u32, u32)> {
+    line.split_whitespace()
+        .filter_map(|field| field.strip_prefix('[')?.strip_suffix(']')?.split_once('/'))
+        .find_map(|(raid_disks, active_disks)| Some((raid_disks.parse().ok()?, active_disks.parse().ok()?)))
+}
+
+/// Parses "[=>....]  recovery =  8.5% (89600/1046528) finish=0.7min speed=22400K/sec"
+/// and "resync=DELAYED" lines.
+fn parse_sync(line: &str) -> Option<MdSync> {
+    // skip the "[=>....]" progress bar
+    let line = match line.strip_prefix('[') {
+        Some(line) => line.split_once(']')?.1.trim(),
+        None => line,
+    };
+    let (action, rest) = line.split_once('=')?;
+    let action = action.trim();
+    if !["resync", "recovery", "check", "repair", "reshape"].contains(&action) {
+        return None;
+    }
+
+    let mut sync = MdSync { action: action.to_string(), ..Default::default() };
+    for field in rest.split_whitespace() {
+        if let Some(progress) = field.strip_suffix('%') {
+            sync.progress = progress.parse::<f64>().ok();
+        } else if let Some(finish) = field.strip_prefix("finish=") {
+            sync.finish = Some(finish.to_string());
+        } else if let Some(speed) = field.strip_prefix("speed=") {
+            sync.speed = Some(speed.to_string());
+        }
+    }
+    Some(sync)
+}
+
+/// Splits /proc/mdstat into arrays, skipping the "Personalities" and "unused devices" lines.
+fn parse_mdstat(content: &str) -> Vec<MdArrayInfo> {
+    let mut arrays_info = Vec::new();
+    let mut array_lines: Vec<&str> = Vec::new();
+    for line in content.lines() {
+        if line.starts_with("md") {
+            arrays_info.extend(MdArrayInfo::parse(&array_lines));
+            array_lines = vec![line];
+        } else if !array_lines.is_empty() && line.starts_with(char::is_whitespace) && !line.trim().is_empty() {
+            array_lines.push(line);
+        }
+    }
+    arrays_info.extend(MdArrayInfo::parse(&array_lines));
+    arrays_info
+}
+
+/// A device-mapper device from /sys/block/dm-*/dm.
+pub struct DmDeviceInfo {
+    device: String,
+    name: String,
+    uuid: String,
+    suspended: bool,
+}
+
+impl Display for DmDeviceInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, " {} ({}): uuid: {}", self.device, self.name, self.uuid)?;
+        if self.suspended {
+            write!(f, " [SUSPENDED]")?;
+        }
+        Ok(())
+    }
+}
+
+fn read_string(path: &Path) -> Option<String> {
+    fs::read_to_string(path).ok().map(|s| s.trim().to_string())
+}
+
+fn read_dm_devices(paths: &HostPaths) -> Vec<DmDeviceInfo> {
+    let entries = match fs::read_dir(paths.resolve(SYS_BLOCK_PATH)) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut dm_devices_info: Vec<DmDeviceInfo> = entries
+        .flatten()
+        .filter(|entry| entry.file_name().to_string_lossy().starts_with("dm-"))
+        .filter_map(|entry| {
+            let dm_path = entry.path().join("dm");
+            Some(DmDeviceInfo {
+                device: entry.file_name().to_string_lossy().to_string(),
+                name: read_string(&dm_path.join("name"))?,
+                uuid: read_string(&dm_path.join("uuid")).unwrap_or_default(),
+                suspended: read_string(&dm_path.join("suspended")).is_some_and(|s| s == "1"),
+            })
+        })
+        .collect();
+    dm_devices_info.sort_by(|a, b| a.device.cmp(&b.device));
+    dm_devices_info
+}
+
+/// Array state remembered between samples to detect changes.
+struct MdArrayState {
+    state: String,
+    degraded: bool,
+    sync_action: Option<String>,
+}
+
+pub static COLLECTOR: CollectorSpec = CollectorSpec {
+    name: "storage",
+    section: "storage_config",
+    title: "Storage Info",
+    refresh_rate: Duration::from_secs(30),
+    default_options: toml::Table::new,
+    new: |_, paths| Ok(Box::new(StorageInfoCollector::new(paths))),
+};
+
+pub struct StorageInfoCollector {
+    mdstat: Option<File>,
+    arrays_state: Option<HashMap<String, MdArrayState>>,
+    events: Vec<QueueItem>,
+    paths: HostPaths,
+}
+
+impl MetricCollector for StorageInfoCollector {
+    fn collect_info(&mut self) -> QueueItem {
+        match self.get_storage_info() {
+            Ok(storage_info) => QueueItem::sample(&COLLECTOR, &storage_info),
+            Err(e) => QueueItem::Err(ErrInfo::new(format!("storage: failed to read {}: {}", MDSTAT_PATH, e))),
+        }
+    }
+
+    fn collect_events(&mut self) -> Vec<QueueItem> {
+        std::mem::take(&mut self.events)
+    }
+
+    fn is_event_driven(&self) -> bool {
+        self.mdstat.is_some()
+    }
+
+    /// Polls /proc/mdstat, which the md driver signals on every array event
+    /// (member failure, sync start or end), so they are reported immediately.
+    fn wait(&mut self, timeout: Duration) -> Vec<QueueItem> {
+        let fd = match &self.mdstat {
+            Some(mdstat) => mdstat.as_raw_fd(),
+            None => {
+                sleep(timeout);
+                return Vec::new();
+            }
+        };
+
+        let mut poll_fd = libc::pollfd { fd, events: libc::POLLPRI, revents: 0 };
+        let timeout_ms = timeout.as_millis().min(libc::c_int::MAX as u128) as libc::c_int;
+        let res = unsafe { libc::poll(&mut poll_fd, 1, timeout_ms) };
+        if res < 0 {
+            let err = std::io::Error::last_os_error();
+            if err.kind() == std::io::ErrorKind::Interrupted {
+                return Vec::new();
+            }
+            self.mdstat = None;
+            return vec![QueueItem::Err(ErrInfo::new(format!("storage: failed to poll {}: {}", MDSTAT_PATH, err)))];
+        }
+        if poll_fd.revents & libc::POLLPRI == 0 {
+            return Vec::new();
+        }
+
+        // reading the file acknowledges the event
+        match self.read_mdstat() {
+            Ok(arrays_info) => self.update_arrays_state(&arrays_info),
+            Err(e) => vec![QueueItem::Err(ErrInfo::new(format!("storage: failed to read {}: {}", MDSTAT_PATH, e)))],
+        }
+    }
+}
+
+impl StorageInfoCollector {
+    pub fn new(paths: &HostPaths) -> Self {
+        Self {
+            mdstat: File::open(paths.resolve(MDSTAT_PATH)).ok(),
+            arrays_state: None,
+            events: Vec::new(),
+            paths: paths.clone(),
+        }
+    }
+
+    /// Degraded arrays and running syncs found on the first call are only
+    /// flagged in the report, events are sent on later changes.
+    pub fn get_storage_info(&mut self) -> Result<StorageInfo, std::io::Error> {
+        let arrays_info = self.read_mdstat()?;
+        let events = self.update_arrays_state(&arrays_info);
+        self.events.extend(events);
+
+        Ok(StorageInfo { arrays_info, dm_devices_info: read_dm_devices(&self.paths) })
+    }
+
+    /// Without the md driver loaded there is no /proc/mdstat and no arrays.
+    fn read_mdstat(&mut self) -> Result<Vec<MdArrayInfo>, std::io::Error> {
+        let mdstat = match self.mdstat.as_mut() {
+            Some(mdstat) => mdstat,
+            None => return Ok(Vec::new()),
+        };
+        let mut content = String::new();
+        mdstat.seek(SeekFrom::Start(0))?;
+        mdstat.read_to_string(&mut content)?;
+        Ok(parse_mdstat(&content))
+    }
+
+    fn update_arrays_state(&mut self, arrays_info: &[MdArrayInfo]) -> Vec<QueueItem> {
+        let arrays_state: HashMap<String, MdArrayState> = arrays_info
+            .iter()
+            .map(|array_info| (array_info.name.clone(), MdArrayState {
+                state: array_info.state.clone(),
+                degraded: array_info.is_degraded(),
+                sync_action: array_info.sync.as_ref().map(|sync| sync.action.clone()),
+            }))
+            .collect();
+
+        let prev_arrays_state = match self.arrays_state.replace(arrays_state) {
+            Some(prev_arrays_state) => prev_arrays_state,
+            None => return Vec::new(),
+        };
+
+        let mut events = Vec::new();
+        let mut event = |message: String| events.push(QueueItem::Event(EventInfo::new(COLLECTOR.name, message)));
+        for array_info in arrays_info {
+            let prev = match prev_arrays_state.get(&array_info.name) {
+                Some(prev) => prev,
+                None => {
+                    event(format!("array assembled:{}", array_info));
+                    continue;
+                }
+            };
+
+            if array_info.state != prev.state {
+                event(format!("array state changed from {} to {}:{}", prev.state, array_info.state, array_info));
+            }
+            if array_info.is_degraded() && !prev.degraded {
+                event(format!("array degraded:{}", array_info));
+            } else if !array_info.is_degraded() && prev.degraded {
+                event(format!("array no longer degraded:{}", array_info));
+            }
+
+            let sync_action = array_info.sync.as_ref().map(|sync| &sync.action);
+            if sync_action != prev.sync_action.as_ref() {
+                if let Some(prev_action) = &prev.sync_action {
+                    event(format!("array {} finished:{}", prev_action, array_info));
+                }
+                if let Some(sync) = &array_info.sync {
+                    event(format!("array {} started:{}", sync.action, array_info));
+                }
+            }
+        }
+        for name in prev_arrays_state.keys() {
+            if !arrays_info.iter().any(|array_info| &array_info.name == name) {
+                event(format!("array stopped: {}", name));
+            }
+        }
+
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metric::host::fixture_paths;
+    use crate::queue::event_messages;
+    use crate::sample::find_point;
+
+    const HEALTHY: &str = "\
+Personalities : [raid1] [raid6] [raid5] [raid4]
+md0 : active raid1 sdb1[1] sda1[0]
+      1046528 blocks super 1.2 [2/2] [UU]
+
+md2 : active raid5 sde1[3] sdd1[2] sdc1[1]
+      2093056 blocks super 1.2 level 5, 512k chunk, algorithm 2 [3/3] [UUU]
+
+unused devices: <none>
+";
+
+    const DEGRADED: &str = "\
+Personalities : [raid1] [raid6] [raid5] [raid4]
+md0 : active raid1 sdb1[1] sda1[0]
+      1046528 blocks super 1.2 [2/2] [UU]
+
+md2 : active raid5 sde1[3](F) sdd1[2] sdc1[1]
+      2093056 blocks super 1.2 level 5, 512k chunk, algorithm 2 [3/2] [UU_]
+
+unused devices: <none>
+";
+
+    const RECOVERY: &str = "\
+Personalities : [raid1] [raid6] [raid5] [raid4]
+md0 : active raid1 sdb1[1] sda1[0]
+      1046528 blocks super 1.2 [2/2] [UU]
+
+md2 : active raid5 sdf1[4] sde1[3](F) sdd1[2] sdc1[1]
+      2093056 blocks super 1.2 level 5, 512k chunk, algorithm 2 [3/2] [UU_]
+      [=>...................]  recovery =  8.5% (89600/1046528) finish=0.7min speed=22400K/sec
+
+unused devices: <none>
+";
+
+    const RECOVERED: &str = "\
+Personalities : [raid1] [raid6] [raid5] [raid4]
+md0 : active raid1 sdb1[1] sda1[0]
+      1046528 blocks super 1.2 [2/2] [UU]
+
+md2 : active raid5 sdf1[4] sdd1[2] sdc1[1]
+      2093056 blocks super 1.2 level 5, 512k chunk, algorithm 2 [3/3] [UUU]
+
+unused devices: <none>
+";
+
+    const PENDING: &str = "\
+Personalities : [raid1] [raid6] [raid5] [raid4]
+md1 : active (auto-read-only) raid1 sdh1[1] sdg1[0]
+      1046528 blocks super 1.2 [2/2] [UU]
+      \tresync=DELAYED
+
+md127 : inactive sdj1[1](S) sdi1[0](S)
+      2093056 blocks super 1.2
+
+unused devices: <none>
+";
+
+    #[test]
+    fn healthy_raid1() {
+        let arrays_info = parse_mdstat(HEALTHY);
+        assert_eq!(arrays_info.len(), 2);
+        let md0 = &arrays_info[0];
+        assert_eq!((md0.name.as_str(), md0.state.as_str(), md0.level.as_str()), ("md0", "active", "raid1"));
+        assert_eq!(md0.members, ["sdb1", "sda1"]);
+        assert_eq!((md0.raid_disks, md0.active_disks), (2, 2));
+        assert!(md0.failed_members.is_empty() && md0.spare_members.is_empty());
+        assert!(md0.sync.is_none());
+        assert!(!md0.is_degraded());
+        assert_eq!(md0.to_string(), " md0: state: active, level: raid1, disks: 2/2, members: sdb1 sda1");
+    }
+
+    #[test]
+    fn degraded_raid5() {
+        let md2 = &parse_mdstat(DEGRADED)[1];
+        assert_eq!((md2.raid_disks, md2.active_disks), (3, 2));
+        assert_eq!(md2.members, ["sdd1", "sdc1"]);
+        assert_eq!(md2.failed_members, ["sde1"]);
+        assert!(md2.is_degraded());
+    }
+
+    #[test]
+    fn recovery_progress() {
+        let md2 = &parse_mdstat(RECOVERY)[1];
+        let sync = md2.sync.as_ref().unwrap();
+        assert_eq!(sync.action, "recovery");
+        assert_eq!(sync.progress, Some(8.5));
+        assert_eq!(sync.finish.as_deref(), Some("0.7min"));
+        assert_eq!(sync.speed.as_deref(), Some("22400K/sec"));
+        assert_eq!(md2.members, ["sdf1", "sdd1", "sdc1"]);
+        assert!(md2.is_degraded());
+    }
+
+    #[test]
+    fn delayed_resync_and_inactive_array() {
+        let arrays_info = parse_mdstat(PENDING);
+        let md1 = &arrays_info[0];
+        assert_eq!(md1.state, "active (auto-read-only)");
+        assert_eq!(md1.level, "raid1");
+        let sync = md1.sync.as_ref().unwrap();
+        assert_eq!((sync.action.as_str(), sync.progress), ("resync", None));
+        assert_eq!(sync.to_string(), "resync: pending");
+
+        let md127 = &arrays_info[1];
+        assert_eq!(md127.state, "inactive");
+        assert_eq!(md127.level, "");
+        assert_eq!(md127.spare_members, ["sdj1", "sdi1"]);
+        assert_eq!((md127.raid_disks, md127.active_disks), (0, 0));
+        assert!(!md127.is_degraded());
+    }
+
+    #[test]
+    fn events_between_snapshots() {
+        let mut collector = StorageInfoCollector::new(&fixture_paths("kvm-guest/t0"));
+        let mut events = |mdstat: &str| {
+            let events = collector.update_arrays_state(&parse_mdstat(mdstat));
+            event_messages(&events).iter().map(|message| message.split(':').next().unwrap().to_string()).collect::<Vec<_>>()
+        };
+
+        assert!(events(HEALTHY).is_empty());
+        assert_eq!(events(DEGRADED), ["array degraded"]);
+        assert_eq!(events(RECOVERY), ["array recovery started"]);
+        assert!(events(RECOVERY).is_empty());
+        assert_eq!(events(RECOVERED), ["array no longer degraded", "array recovery finished"]);
+        assert_eq!(events(PENDING), ["array assembled", "array assembled", "array stopped", "array stopped"]);
+
+        events(HEALTHY);
+        let read_only = HEALTHY.replace("md0 : active raid1", "md0 : active (read-only) raid1");
+        assert_eq!(events(&read_only), ["array state changed from active to active (read-only)"]);
+    }
+
+    #[test]
+    fn array_points() {
+        let storage_info = StorageInfo { arrays_info: parse_mdstat(PENDING), dm_devices_info: Vec::new() };
+        let points = storage_info.points();
+        let md1 = [("array", "md1"), ("level", "raid1")];
+        assert_eq!(find_point(&points, "md_active", &md1).unwrap().get_value(), 1.0);
+        assert_eq!(find_point(&points, "md_read_only", &md1).unwrap().get_value(), 1.0);
+        assert_eq!(find_point(&points, "md_sync_progress", &md1).unwrap().get_value(), 0.0);
+        assert_eq!(find_point(&points, "md_active", &[("array", "md127")]).unwrap().get_value(), 0.0);
+        // only the array and its level identify a point
+        assert!(points.iter().all(|point| point.get_labels().iter().all(|(key, _)| *key == "array" || *key == "level")));
+    }
+}
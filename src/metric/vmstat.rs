@@ -0,0 +1,180 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::{from_options, to_options};
+use crate::queue::{ErrInfo, QueueItem};
+use crate::metric::{CollectorSpec, MetricCollector};
+use crate::metric::host::{elapsed_secs, HostPaths};
+use crate::sample::{Point, SampleSource, Unit};
+
+
+const VMSTAT_PATH: &str = "/proc/vmstat";
+
+/// Per second rates of the allowed /proc/vmstat counters.
+pub struct VmStatInfo {
+    counters: Vec<(String, f64)>,
+}
+
+impl Display for VmStatInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (name, rate) in &self.counters {
+            writeln!(f, " {}: {:.2}/s", name, rate)?;
+        }
+        Ok(())
+    }
+}
+
+impl SampleSource for VmStatInfo {
+    fn points(&self) -> Vec<Point> {
+        self.counters.iter().map(|(name, rate)| Point::rate(format!("vmstat_{}", name), Unit::Count, *rate)).collect()
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(default)]
+pub struct VmStatConfig {
+    counters: Vec<String>,
+}
+
+impl Default for VmStatConfig {
+    fn default() -> Self {
+        VmStatConfig {
+            counters: [
+                "pgfault", "pgmajfault", "pswpin", "pswpout", "pgscan_*", "pgsteal_*",
+                "compact_stall", "thp_fault_fallback", "thp_collapse_alloc_failed", "oom_kill",
+            ].iter().map(|counter| counter.to_string()).collect(),
+        }
+    }
+}
+
+impl VmStatConfig {
+    /// Allowlist of /proc/vmstat counters, a trailing `*` matches by prefix.
+    pub fn get_counters(&self) -> &Vec<String> {
+        &self.counters
+    }
+}
+
+pub static COLLECTOR: CollectorSpec = CollectorSpec {
+    name: "vmstat",
+    section: "vmstat_config",
+    title: "VmStat Info",
+    refresh_rate: Duration::from_secs(1),
+    default_options: || to_options(&VmStatConfig::default()),
+    new: |options, paths| Ok(Box::new(VmStatInfoCollector::new(&from_options(options)?, paths))),
+};
+
+pub struct VmStatInfoCollector {
+    counters: Vec<String>,
+    prev_stats: HashMap<String, u64>,
+    prev_time: Option<f64>,
+    paths: HostPaths,
+}
+
+impl MetricCollector for VmStatInfoCollector {
+    fn collect_info(&mut self) -> QueueItem {
+        match self.get_vmstat_info() {
+            Ok(vmstat_info) => QueueItem::sample(&COLLECTOR, &vmstat_info),
+            Err(e) => QueueItem::Err(ErrInfo::new(format!("vmstat: failed to read {}: {}", VMSTAT_PATH, e))),
+        }
+    }
+
+    fn reset_counters(&mut self) {
+        self.prev_stats.clear();
+        self.prev_time = None;
+    }
+}
+
+impl VmStatInfoCollector {
+    pub fn new(config: &VmStatConfig, paths: &HostPaths) -> Self {
+        Self {
+            counters: config.get_counters().clone(),
+            prev_stats: HashMap::new(),
+            prev_time: None,
+            paths: paths.clone(),
+        }
+    }
+
+    /// Rates are computed since the previous call and reported as zero on the first one.
+    pub fn get_vmstat_info(&mut self) -> Result<VmStatInfo, std::io::Error> {
+        let vmstat = fs::read_to_string(self.paths.resolve(VMSTAT_PATH))?;
+        let now = self.paths.read_uptime();
+        let elapsed_secs = elapsed_secs(self.prev_time, now);
+
+        let mut stats_map = HashMap::new();
+        for line in vmstat.lines() {
+            let (name, value) = match line.split_once(' ') {
+                Some(field) => field,
+                None => continue,
+            };
+            if !self.is_counter_allowed(name) {
+                continue;
+            }
+            stats_map.insert(name.to_string(), value.trim().parse::<u64>().unwrap_or(0));
+        }
+
+        // keep the order of the allowlist, names matched by a wildcard sorted
+        let mut counters = Vec::new();
+        for pattern in &self.counters {
+            let mut names: Vec<&String> = stats_map
+                .keys()
+                .filter(|name| matches_counter(pattern, name) && !counters.iter().any(|(c, _)| c == *name))
+                .collect();
+            names.sort();
+
+            for name in names {
+                let value = stats_map[name];
+                let prev = self.prev_stats.get(name).copied().unwrap_or(value);
+                let rate = if elapsed_secs > 0.0 { value.saturating_sub(prev) as f64 / elapsed_secs } else { 0.0 };
+                counters.push((name.clone(), rate));
+            }
+        }
+
+        self.prev_stats = stats_map;
+        self.prev_time = now;
+
+        Ok(VmStatInfo { counters })
+    }
+
+    fn is_counter_allowed(&self, name: &str) -> bool {
+        self.counters.iter().any(|pattern| matches_counter(pattern, name))
+    }
+}
+
+/// Counter names are matched exactly, or by prefix when the pattern ends with `*`.
+fn matches_counter(pattern: &str, name: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => name.starts_with(prefix),
+        None => pattern == name,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metric::host::{assert_close, fixture_paths};
+
+    #[test]
+    fn counter_rates_between_snapshots() {
+        let mut collector = VmStatInfoCollector::new(&VmStatConfig::default(), &fixture_paths("kvm-guest/t0"));
+        collector.get_vmstat_info().unwrap();
+        collector.paths = fixture_paths("kvm-guest/t1");
+        let vmstat_info = collector.get_vmstat_info().unwrap();
+
+        let rate = |name: &str| vmstat_info.counters.iter().find(|(counter, _)| counter == name).map(|(_, rate)| *rate);
+        assert_close(rate("pgfault").unwrap(), 31716.0 / 1.71);
+        assert_close(rate("pgmajfault").unwrap(), 28.0 / 1.71);
+        assert_close(rate("pswpin").unwrap(), 0.0);
+        assert_eq!(rate("pgpgin"), None);
+
+        // the allowlist order is kept, wildcard matches are sorted
+        let names: Vec<&str> = vmstat_info.counters.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(&names[..4], ["pgfault", "pgmajfault", "pswpin", "pswpout"]);
+        let pgscan: Vec<&&str> = names.iter().filter(|name| name.starts_with("pgscan_")).collect();
+        assert!(!pgscan.is_empty());
+        assert!(pgscan.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+}
@@ -1,18 +1,24 @@
 use std::sync::{Arc, Mutex, Condvar};
 use std::collections::VecDeque;
 use std::fmt::{Display, Formatter};
-use crate::metric::cpu::CpuInfo;
-use crate::metric::memory::MemoryInfo;
-use crate::metric::io::IOInfo;
+use crate::metric::kmsg::KmsgEvent;
+use crate::metric::CollectorSpec;
+use crate::sample::{Sample, SampleSource};
 
 
 pub enum QueueItem {
-        CPU(CpuInfo),
-        Memory(MemoryInfo),
-        IO(IOInfo),
+        Sample(Sample),
+        KernelEvent(KmsgEvent),
+        Event(EventInfo),
         Err(ErrInfo),
 }
 
+impl QueueItem {
+    pub fn sample(spec: &'static CollectorSpec, source: &impl SampleSource) -> QueueItem {
+        QueueItem::Sample(Sample::new(spec, source))
+    }
+}
+
 pub struct ErrInfo {
     error: String,
 }
@@ -30,11 +36,55 @@ impl ErrInfo {
     }
 }
 
+pub struct EventInfo {
+    metric: &'static str,
+    event: String,
+}
+
+impl Display for EventInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "EventInfo [{}]: {}", self.metric, self.event)?;
+        Ok(())
+    }
+}
+
+impl EventInfo {
+    pub fn new(metric: &'static str, event: String) -> EventInfo {
+        EventInfo { metric, event }
+    }
+
+    pub fn get_metric(&self) -> &'static str {
+        self.metric
+    }
+
+    pub fn get_event(&self) -> &str {
+        &self.event
+    }
+}
+
+/// Messages of the events among `items` in tests.
+#[cfg(test)]
+pub(crate) fn event_messages(items: &[QueueItem]) -> Vec<&str> {
+    items
+        .iter()
+        .filter_map(|item| match item {
+            QueueItem::Event(event) => Some(event.get_event()),
+            _ => None,
+        })
+        .collect()
+}
+
 pub struct Queue {
     queue: Arc<Mutex<VecDeque<QueueItem>>>,
     cond: Arc<Condvar>,
 }
 
+impl Default for Queue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Queue {
     pub fn new() -> Self {
         Queue {
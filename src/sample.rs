@@ -0,0 +1,268 @@
+use std::ffi::CStr;
+use std::fmt::{Display, Formatter};
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime};
+
+use crate::metric::CollectorSpec;
+
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum ValueKind {
+    /// A value that can go up and down, e.g. free memory.
+    Gauge,
+    /// A monotonically increasing total, e.g. oom kills since boot.
+    Counter,
+    /// Change of a counter per second between two collector runs.
+    Rate,
+}
+
+pub fn kind_to_str(kind: ValueKind) -> &'static str {
+    match kind {
+        ValueKind::Gauge => "gauge",
+        ValueKind::Counter => "counter",
+        ValueKind::Rate => "rate",
+    }
+}
+
+/// Unit of a value, rates are per second of the given unit, e.g. bytes per second.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum Unit {
+    Count,
+    Percent,
+    Ratio,
+    Bytes,
+    Pages,
+    Seconds,
+    Milliseconds,
+    Microseconds,
+    Hertz,
+    BitsPerSecond,
+    Celsius,
+    Rpm,
+    Volts,
+}
+
+pub fn unit_to_str(unit: Unit) -> &'static str {
+    match unit {
+        Unit::Count => "count",
+        Unit::Percent => "percent",
+        Unit::Ratio => "ratio",
+        Unit::Bytes => "bytes",
+        Unit::Pages => "pages",
+        Unit::Seconds => "seconds",
+        Unit::Milliseconds => "milliseconds",
+        Unit::Microseconds => "microseconds",
+        Unit::Hertz => "hertz",
+        Unit::BitsPerSecond => "bits_per_second",
+        Unit::Celsius => "celsius",
+        Unit::Rpm => "rpm",
+        Unit::Volts => "volts",
+    }
+}
+
+/// A single typed value of a sample, e.g. the rx rate of an interface with
+/// the label `interface=eth0`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Point {
+    name: String,
+    labels: Vec<(&'static str, String)>,
+    kind: ValueKind,
+    unit: Unit,
+    value: f64,
+}
+
+impl Point {
+    pub fn new(name: impl Into<String>, kind: ValueKind, unit: Unit, value: f64) -> Self {
+        Self {
+            name: name.into(),
+            labels: Vec::new(),
+            kind,
+            unit,
+            value,
+        }
+    }
+
+    pub fn gauge(name: impl Into<String>, unit: Unit, value: f64) -> Self {
+        Self::new(name, ValueKind::Gauge, unit, value)
+    }
+
+    pub fn counter(name: impl Into<String>, unit: Unit, value: f64) -> Self {
+        Self::new(name, ValueKind::Counter, unit, value)
+    }
+
+    pub fn rate(name: impl Into<String>, unit: Unit, value: f64) -> Self {
+        Self::new(name, ValueKind::Rate, unit, value)
+    }
+
+    pub fn label(mut self, key: &'static str, value: impl Into<String>) -> Self {
+        self.labels.push((key, value.into()));
+        self
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_labels(&self) -> &[(&'static str, String)] {
+        &self.labels
+    }
+
+    pub fn get_label(&self, key: &str) -> Option<&str> {
+        self.labels.iter().find(|(k, _)| *k == key).map(|(_, value)| value.as_str())
+    }
+
+    pub fn get_kind(&self) -> ValueKind {
+        self.kind
+    }
+
+    pub fn get_unit(&self) -> Unit {
+        self.unit
+    }
+
+    pub fn get_value(&self) -> f64 {
+        self.value
+    }
+}
+
+impl Display for Point {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name)?;
+        if !self.labels.is_empty() {
+            let labels: Vec<String> = self.labels.iter().map(|(key, value)| format!("{}=\"{}\"", key, value)).collect();
+            write!(f, "{{{}}}", labels.join(","))?;
+        }
+        write!(f, " {} ({} {})", self.value, kind_to_str(self.kind), unit_to_str(self.unit))
+    }
+}
+
+/// Collector output that can be sent as a sample, the `Display` text is what
+/// gets logged for it.
+pub trait SampleSource: Display {
+    fn points(&self) -> Vec<Point>;
+}
+
+/// Output of a collector run with the time it was taken on and the host it was
+/// taken from.
+pub struct Sample {
+    spec: &'static CollectorSpec,
+    /// Time since boot from CLOCK_MONOTONIC, for ordering samples and computing
+    /// intervals unaffected by clock changes.
+    monotonic: Duration,
+    timestamp: SystemTime,
+    hostname: &'static str,
+    points: Vec<Point>,
+    message: String,
+}
+
+impl Sample {
+    pub fn new(spec: &'static CollectorSpec, source: &impl SampleSource) -> Self {
+        Self {
+            spec,
+            monotonic: monotonic_now(),
+            timestamp: SystemTime::now(),
+            hostname: hostname(),
+            points: source.points(),
+            message: source.to_string(),
+        }
+    }
+
+    pub fn get_collector(&self) -> &'static str {
+        self.spec.name
+    }
+
+    pub fn get_title(&self) -> &'static str {
+        self.spec.title
+    }
+
+    pub fn get_monotonic(&self) -> Duration {
+        self.monotonic
+    }
+
+    pub fn get_timestamp(&self) -> SystemTime {
+        self.timestamp
+    }
+
+    pub fn get_hostname(&self) -> &'static str {
+        self.hostname
+    }
+
+    pub fn get_points(&self) -> &[Point] {
+        &self.points
+    }
+
+    /// Human readable text of the collector output.
+    pub fn get_message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl Display for Sample {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:\n{}", self.spec.title, self.message)
+    }
+}
+
+fn monotonic_now() -> Duration {
+    let mut ts = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+    unsafe {
+        libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut ts);
+    }
+    Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32)
+}
+
+/// Hostname of the monitor, read once as samples are taken every second.
+fn hostname() -> &'static str {
+    static HOSTNAME: OnceLock<String> = OnceLock::new();
+    HOSTNAME.get_or_init(|| {
+        let mut buf = [0u8; 256];
+        let ret = unsafe { libc::gethostname(buf.as_mut_ptr() as *mut libc::c_char, buf.len()) };
+        if ret != 0 {
+            return String::from("unknown");
+        }
+        CStr::from_bytes_until_nul(&buf)
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|_| String::from("unknown"))
+    })
+}
+
+/// Find a point by name and labels in tests.
+#[cfg(test)]
+pub(crate) fn find_point<'a>(points: &'a [Point], name: &str, labels: &[(&str, &str)]) -> Option<&'a Point> {
+    points.iter().find(|point| {
+        point.name == name && labels.iter().all(|(key, value)| point.get_label(key) == Some(value))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metric::find_collector;
+
+    struct TestInfo;
+
+    impl Display for TestInfo {
+        fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+            writeln!(f, "test")
+        }
+    }
+
+    impl SampleSource for TestInfo {
+        fn points(&self) -> Vec<Point> {
+            vec![Point::rate("rx", Unit::Bytes, 1.5).label("interface", "eth0")]
+        }
+    }
+
+    #[test]
+    fn sample_carries_time_host_and_points() {
+        let spec = find_collector("net").unwrap();
+        let first = Sample::new(spec, &TestInfo);
+        let second = Sample::new(spec, &TestInfo);
+
+        assert_eq!(first.get_collector(), "net");
+        assert!(second.get_monotonic() >= first.get_monotonic());
+        assert!(first.get_timestamp() > SystemTime::UNIX_EPOCH);
+        assert!(!first.get_hostname().is_empty());
+        assert_eq!(first.to_string(), "Network Info:\ntest\n");
+        assert_eq!(first.get_points()[0].to_string(), "rx{interface=\"eth0\"} 1.5 (rate bytes)");
+    }
+}